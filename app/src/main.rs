@@ -6,21 +6,126 @@ extern crate image;
 #[macro_use]
 extern crate clap;
 
-use std::fs::File;
-use clap::App;
+use std::path::{Path, PathBuf};
+use std::process;
+use clap::{App, ArgMatches};
+use raytracer::scene::Scene;
+use raytracer::lint;
+use raytracer::aov::Pass;
+use raytracer::integrator::Integrator;
 
 fn main() {
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml).get_matches();
 
-    let scene_path = matches.value_of("scene").unwrap();
-    let scene_file = File::open(scene_path).expect("File not found");
+    match matches.subcommand() {
+        ("validate", Some(sub_matches)) => validate(sub_matches),
+        ("convert", Some(sub_matches)) => convert(sub_matches),
+        _ => render(&matches)
+    }
+}
 
+fn render(matches: &ArgMatches) {
+    let scene_path = Path::new(matches.value_of("scene").unwrap());
     let image_path = matches.value_of("output").unwrap();
+    let search_paths = search_paths(matches, scene_path);
+
+    let scene = Scene::load_with_search_paths(scene_path, &search_paths).unwrap_or_else(|e| {
+        eprintln!("Error in {}: {}", scene_path.display(), e);
+        process::exit(1);
+    });
+
+    let passes = parse_passes(matches, &scene);
+
+    let (img, pass_images) = raytracer::render_with_passes(&scene, &passes);
+
+    if let Err(e) = img.save(image_path) {
+        eprintln!("Unable to save {}: {}", image_path, e);
+        process::exit(1);
+    }
+
+    for pass_image in pass_images.iter() {
+        let pass_path = Path::new(image_path).with_extension(format!("{}.hdr", pass_image.pass.name()));
+
+        if let Err(e) = pass_image.save(&pass_path) {
+            eprintln!("Unable to save {}: {}", pass_path.display(), e);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_passes(matches: &ArgMatches, scene: &Scene) -> Vec<Pass> {
+    let names = match matches.values_of("pass") {
+        Some(names) => names,
+        None => return Vec::new()
+    };
+
+    let passes: Vec<Pass> = names.map(|name| {
+        let pass = name.parse().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        });
+
+        if let Pass::Light(index) = pass {
+            if index >= scene.lights.len() {
+                eprintln!("Error: pass {} refers to missing light", name);
+                process::exit(1);
+            }
+        }
+
+        pass
+    }).collect();
 
-    let scene = serde_json::from_reader(scene_file).unwrap();
+    if passes.iter().any(|pass| pass.is_lighting()) && !matches!(scene.integrator, Integrator::Path(_)) {
+        eprintln!("Warning: lighting passes are only rendered by Path integrator, they will be black");
+    }
 
-    let img = raytracer::render(&scene);
+    passes
+}
+
+fn validate(matches: &ArgMatches) {
+    let scene_path = Path::new(matches.value_of("scene").unwrap());
+    let search_paths = search_paths(matches, scene_path);
+
+    let scene = Scene::parse(scene_path).unwrap_or_else(|e| {
+        eprintln!("Error in {}: {}", scene_path.display(), e);
+        process::exit(1);
+    });
+
+    let issues = lint::lint(&scene, &search_paths);
+
+    for issue in issues.iter() {
+        println!("{}", issue);
+    }
+
+    if issues.iter().any(|issue| issue.is_error()) {
+        process::exit(1);
+    }
+
+    if issues.is_empty() {
+        println!("{} is valid", scene_path.display());
+    }
+}
+
+fn convert(matches: &ArgMatches) {
+    let scene_path = Path::new(matches.value_of("scene").unwrap());
+    let output_path = Path::new(matches.value_of("output").unwrap());
+
+    let scene = Scene::parse(scene_path).unwrap_or_else(|e| {
+        eprintln!("Error in {}: {}", scene_path.display(), e);
+        process::exit(1);
+    });
+
+    if let Err(e) = scene.save(output_path) {
+        eprintln!("Unable to save {}: {}", output_path.display(), e);
+        process::exit(1);
+    }
+}
 
-    img.save(image_path).unwrap();
+// Directories listed with -I or the scene file folder
+fn search_paths(matches: &ArgMatches, scene_path: &Path) -> Vec<PathBuf> {
+    match matches.values_of("search_path") {
+        Some(paths) => paths.map(PathBuf::from).collect(),
+        None => vec![scene_path.parent().unwrap_or(Path::new("")).to_path_buf()]
+    }
 }
@@ -0,0 +1,56 @@
+// Stops sampling pixels once their noise is low enough. Time saved on converged pixels, e.g.
+// flat sky, goes to noisy ones which can take up to max_samples
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdaptiveSampling {
+    // Samples every pixel gets before its noise is checked
+    #[serde(default="AdaptiveSampling::default_min_samples")]
+    pub min_samples: u32,
+    // Upper bound for noisy pixels, defaults to camera.samples
+    #[serde(default)]
+    pub max_samples: Option<u32>,
+    // Standard error of pixel brightness relative to the brightness itself at which pixel is done
+    #[serde(default="AdaptiveSampling::default_threshold")]
+    pub threshold: f64,
+}
+
+// Running mean and variance of pixel brightness
+#[derive(Default)]
+pub struct PixelStats {
+    pub count: u32,
+    mean: f64,
+    m2: f64,
+}
+
+// Brightness below which noise is compared to this value instead, otherwise
+// nearly black pixels would never converge
+const MIN_LUMINANCE: f64 = 0.05;
+
+impl AdaptiveSampling {
+    fn default_min_samples() -> u32 { 16 }
+    fn default_threshold() -> f64 { 0.02 }
+
+    pub fn converged(&self, stats: &PixelStats) -> bool {
+        stats.count >= self.min_samples.max(2) && stats.standard_error() <= self.threshold * stats.mean.max(MIN_LUMINANCE)
+    }
+}
+
+impl PixelStats {
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    // Expected error of the mean
+    pub fn standard_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+
+        let variance = self.m2 / (self.count - 1) as f64;
+
+        (variance / self.count as f64).sqrt()
+    }
+}
@@ -0,0 +1,176 @@
+use color::Color;
+use geometry::Intersectable;
+use image::Rgb;
+use integrator::Components;
+use image::hdr::HDREncoder;
+use ray::Ray;
+use scene::Scene;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+
+// Auxiliary buffer rendered next to the beauty image for compositing. Geometric passes are
+// taken at the first hit of the ray through the pixel center, pixels showing the sky are black.
+// Lighting passes are averaged over all camera samples like the beauty image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pass {
+    // Distance from the camera along the ray
+    Depth,
+    // World space shading normal mapped from -1..1 to 0..1
+    Normal,
+    // Surface color without any lighting
+    Albedo,
+    // Texture coordinates wrapped to 0..1 in red and green
+    Uv,
+    // Index of the object in scene geometry plus one
+    Object,
+    // Index of the material plus one. Objects using the same named material share it
+    Material,
+    // Lighting passes of Path integrator, together they add up to the beauty image. Emission
+    // is the sky, lights and emissive surfaces seen directly
+    Emission,
+    DirectDiffuse,
+    IndirectDiffuse,
+    Reflection,
+    Refraction,
+    // Light coming from scene light with given index, through any path
+    Light(usize),
+    // Number of camera samples the pixel got, it only varies with adaptive sampling
+    Samples,
+}
+
+// Float image of one pass
+pub struct PassImage {
+    pub pass: Pass,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>,
+}
+
+impl Pass {
+    // All passes except per light ones
+    pub fn all() -> Vec<Pass> {
+        vec![Pass::Depth, Pass::Normal, Pass::Albedo, Pass::Uv, Pass::Object, Pass::Material,
+             Pass::Emission, Pass::DirectDiffuse, Pass::IndirectDiffuse, Pass::Reflection, Pass::Refraction,
+             Pass::Samples]
+    }
+
+    // Name used on command line and in file names
+    pub fn name(&self) -> String {
+        match *self {
+            Pass::Depth => "depth".to_string(),
+            Pass::Normal => "normal".to_string(),
+            Pass::Albedo => "albedo".to_string(),
+            Pass::Uv => "uv".to_string(),
+            Pass::Object => "object".to_string(),
+            Pass::Material => "material".to_string(),
+            Pass::Emission => "emission".to_string(),
+            Pass::DirectDiffuse => "direct_diffuse".to_string(),
+            Pass::IndirectDiffuse => "indirect_diffuse".to_string(),
+            Pass::Reflection => "reflection".to_string(),
+            Pass::Refraction => "refraction".to_string(),
+            Pass::Light(index) => format!("light{}", index),
+            Pass::Samples => "samples".to_string(),
+        }
+    }
+
+    pub fn is_lighting(&self) -> bool {
+        matches!(*self, Pass::Emission | Pass::DirectDiffuse | Pass::IndirectDiffuse |
+                        Pass::Reflection | Pass::Refraction | Pass::Light(_))
+    }
+
+    // Value of lighting pass for one camera sample, nothing for geometric passes
+    pub fn lighting_value(&self, components: &Components) -> Option<Color> {
+        match *self {
+            Pass::Emission => Some(components.emission.clone()),
+            Pass::DirectDiffuse => Some(components.direct_diffuse.clone()),
+            Pass::IndirectDiffuse => Some(components.indirect_diffuse.clone()),
+            Pass::Reflection => Some(components.reflection.clone()),
+            Pass::Refraction => Some(components.refraction.clone()),
+            Pass::Light(index) => Some(components.lights.get(index).cloned().unwrap_or_else(Color::black)),
+            _ => None
+        }
+    }
+}
+
+impl FromStr for Pass {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Pass, String> {
+        if let Some(index) = name.strip_prefix("light").and_then(|index| index.parse().ok()) {
+            return Ok(Pass::Light(index));
+        }
+
+        Pass::all().into_iter()
+            .find(|pass| pass.name() == name)
+            .ok_or_else(|| format!("unknown pass '{}'", name))
+    }
+}
+
+impl PassImage {
+    // Writes pass as Radiance HDR file, so values aren't clamped or quantized
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let pixels: Vec<_> = self.pixels.iter()
+            .map(|c| Rgb { data: [c.r as f32, c.g as f32, c.b as f32] })
+            .collect();
+
+        HDREncoder::new(file).encode(&pixels, self.width as usize, self.height as usize)
+    }
+}
+
+// Material index of every object. Named materials get one index for all objects using them
+pub fn material_ids(scene: &Scene) -> Vec<usize> {
+    let mut named = HashMap::new();
+    let mut count = 0;
+
+    scene.geometry.iter().map(|object| {
+        let id = match object.material().name {
+            Some(ref name) => *named.entry(name.as_str()).or_insert(count),
+            None => count
+        };
+
+        if id == count {
+            count += 1;
+        }
+
+        id
+    }).collect()
+}
+
+// Values of all requested passes for the ray
+pub fn values(scene: &Scene, ray: &Ray, passes: &[Pass], material_ids: &[usize]) -> Vec<Color> {
+    let intersection = match scene.trace(ray) {
+        Some(intersection) => intersection,
+        None => return vec![Color::black(); passes.len()]
+    };
+
+    let object = intersection.object;
+    let surface = scene.surface(ray, &intersection);
+    let index = scene.geometry.iter().position(|o| ::std::ptr::eq(o, object)).unwrap_or(0);
+
+    passes.iter().map(|pass| match *pass {
+        Pass::Depth => gray(intersection.distance),
+        Pass::Normal => Color {
+            r: surface.normal.x * 0.5 + 0.5,
+            g: surface.normal.y * 0.5 + 0.5,
+            b: surface.normal.z * 0.5 + 0.5,
+        },
+        Pass::Albedo => object.material().color_at(&surface.coords),
+        // Surface coords only have uv when the material uses textures, the pass needs them anyway
+        Pass::Uv => {
+            let uv = object.texture_coords(&surface.point);
+
+            Color { r: uv.x - uv.x.floor(), g: uv.y - uv.y.floor(), b: 0.0 }
+        },
+        Pass::Object => gray((index + 1) as f64),
+        Pass::Material => gray((material_ids[index] + 1) as f64),
+        _ => Color::black()
+    }).collect()
+}
+
+fn gray(value: f64) -> Color {
+    Color { r: value, g: value, b: value }
+}
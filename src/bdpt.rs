@@ -0,0 +1,723 @@
+use color::Color;
+use film::Splats;
+use geometry::Object;
+use integrator;
+use light::{Light, LightSample};
+use material::{Material, RayBehavior};
+use ray::Ray;
+use sampling;
+use scene::Scene;
+use texture::TextureCoords;
+use vector::{Vector3, Point};
+use sampler::Sampler;
+use std::f64::consts::PI;
+use std::ptr;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Bidirectional {
+    // Maximum number of bounces of a connected path
+    #[serde(default="Bidirectional::default_max_depth")]
+    pub max_depth: u32,
+}
+
+// Light source light subpaths can start from. Directional lights have no position
+// to start from, they are only sampled from camera subpaths
+struct Emitter<'a> {
+    shape: Shape<'a>,
+    light: Option<&'a Light>,
+    object: Option<&'a Object>,
+}
+
+enum Shape<'a> {
+    // Point light emitting the same intensity in all directions
+    Point { position: &'a Point, intensity: Color },
+    // Sphere emitting the same radiance from every point of its surface
+    Sphere { center: &'a Point, radius: f64, radiance: Color },
+}
+
+// Light leaving an emitter picked for the start of light subpath
+struct Emission {
+    point: Point,
+    normal: Vector3,
+    direction: Vector3,
+    radiance: Color,
+    pdf_position: f64,
+    pdf_direction: f64,
+}
+
+#[derive(Clone)]
+enum Kind<'a> {
+    Camera,
+    Light,
+    Surface { material: &'a Material, coords: TextureCoords },
+}
+
+#[derive(Clone)]
+struct Vertex<'a> {
+    kind: Kind<'a>,
+    point: Point,
+    // Zero for camera and point lights, which aren't on any surface
+    normal: Vector3,
+    // Index of emitter the vertex lies on
+    emitter: Option<usize>,
+    // Path contribution up to the vertex divided by its density
+    beta: Color,
+    // Ray leaving the vertex was picked by specular reflection or refraction
+    delta: bool,
+    // Area density of the vertex being picked by its own subpath and by the opposite one
+    pdf_fwd: f64,
+    pdf_rev: f64,
+}
+
+impl Default for Bidirectional {
+    fn default() -> Bidirectional {
+        Bidirectional { max_depth: Bidirectional::default_max_depth() }
+    }
+}
+
+impl Bidirectional {
+    fn default_max_depth() -> u32 { 8 }
+
+    // Traces camera subpath along the ray and one light subpath, then connects all pairs of their
+    // vertices. Each connection is weighted against other ways to build the same path with power
+    // heuristic. Connections of light subpath to the camera land on other pixels and go to splats
+    pub fn radiance(&self, scene: &Scene, ray: &Ray, splats: &Splats, sampler: &mut Sampler) -> Color {
+        let emitters = emitters(scene);
+        let max_depth = self.max_depth as usize;
+
+        let mut color = Color::black();
+        let (camera_path, escaped) = camera_subpath(scene, &emitters, ray, max_depth + 2, sampler);
+        let light_path = light_subpath(scene, &emitters, max_depth + 1, sampler);
+
+        color = color.add_color(&escaped);
+        color = color.add_color(&directional_light(scene, &camera_path, max_depth, sampler));
+
+        for t in 1..camera_path.len() + 1 {
+            for s in 0..light_path.len() + 1 {
+                let depth = s + t;
+
+                if (s == 1 && t == 1) || depth < 2 || depth - 2 > max_depth {
+                    continue;
+                }
+
+                if t == 1 {
+                    if let Some((x, y, splat)) = connect_to_camera(scene, &emitters, &light_path, &camera_path, s) {
+                        splats.add(x, y, &splat);
+                    }
+                } else {
+                    color = color.add_color(&connect(scene, &emitters, &light_path, &camera_path, s, t, sampler));
+                }
+            }
+        }
+
+        color
+    }
+}
+
+fn emitters(scene: &Scene) -> Vec<Emitter<'_>> {
+    let mut emitters = Vec::new();
+
+    for light in scene.lights.iter() {
+        if let Light::SphericalLight(ref s) = *light {
+            let shape = if s.radius > 0.0 {
+                Shape::Sphere { center: &s.position, radius: s.radius, radiance: light.radiance() }
+            } else {
+                Shape::Point { position: &s.position, intensity: s.color.multiply(s.intensity / (4.0 * PI)) }
+            };
+
+            emitters.push(Emitter { shape, light: Some(light), object: None });
+        }
+    }
+
+    for object in scene.geometry.iter() {
+        if let Object::Sphere(ref s) = *object {
+            if !s.material.emission.is_black() {
+                let shape = Shape::Sphere { center: &s.center, radius: s.radius, radiance: s.material.emission.clone() };
+
+                emitters.push(Emitter { shape, light: None, object: Some(object) });
+            }
+        }
+    }
+
+    emitters
+}
+
+impl<'a> Emitter<'a> {
+    fn is_delta(&self) -> bool {
+        match self.shape {
+            Shape::Point { .. } => true,
+            Shape::Sphere { .. } => false
+        }
+    }
+
+    // Picks point on the emitter and direction of light leaving it. Point lights use
+    // only the first direction
+    fn sample_emission(&self, sampler: &mut Sampler) -> Emission {
+        let first = sampler.direction();
+        let second = sampler.direction();
+
+        match self.shape {
+            Shape::Point { position, ref intensity } => Emission {
+                point: position.clone(),
+                normal: Vector3::zero(),
+                direction: first,
+                radiance: intensity.clone(),
+                pdf_position: 1.0,
+                pdf_direction: 1.0 / (4.0 * PI),
+            },
+            Shape::Sphere { center, radius, ref radiance } => {
+                let normal = first;
+                let direction = normal.add(&second).normalize();
+
+                Emission {
+                    point: center.add(&normal.multiply(radius)),
+                    pdf_position: 1.0 / (4.0 * PI * radius * radius),
+                    pdf_direction: normal.dot(&direction).max(0.0) / PI,
+                    normal,
+                    direction,
+                    radiance: radiance.clone(),
+                }
+            }
+        }
+    }
+
+    // Picks point on the emitter as seen from the point, like Light::sample
+    fn sample_incoming(&self, point: &Point, sampler: &mut Sampler) -> Option<(LightSample, Vertex<'a>)> {
+        let sample = match self.shape {
+            Shape::Point { position, ref intensity } => {
+                let distance = position.distance(point);
+
+                LightSample {
+                    direction: position.subtract(point).normalize(),
+                    distance,
+                    radiance: intensity.divide(distance * distance),
+                    pdf: 1.0,
+                }
+            },
+            Shape::Sphere { center, radius, ref radiance } => {
+                let (direction, pdf) = sampling::sample_sphere(center, radius, point, sampler)?;
+                let distance = sampling::intersect_sphere(center, radius, point, &direction)?;
+
+                LightSample { direction, distance, radiance: radiance.clone(), pdf }
+            }
+        };
+
+        let light_point = point.add(&sample.direction.multiply(sample.distance));
+
+        let vertex = Vertex {
+            kind: Kind::Light,
+            normal: self.normal_at(&light_point),
+            point: light_point,
+            emitter: None,
+            beta: Color::black(),
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        };
+
+        Some((sample, vertex))
+    }
+
+    fn normal_at(&self, point: &Point) -> Vector3 {
+        match self.shape {
+            Shape::Point { .. } => Vector3::zero(),
+            Shape::Sphere { center, .. } => point.subtract(center).normalize()
+        }
+    }
+
+    // Area density of light subpaths starting at any point of the emitter
+    fn pdf_position(&self) -> f64 {
+        match self.shape {
+            Shape::Point { .. } => 0.0,
+            Shape::Sphere { radius, .. } => 1.0 / (4.0 * PI * radius * radius)
+        }
+    }
+
+    // Solid angle density of light leaving the point of emitter with the normal in direction
+    fn pdf_direction(&self, normal: &Vector3, direction: &Vector3) -> f64 {
+        match self.shape {
+            Shape::Point { .. } => 1.0 / (4.0 * PI),
+            Shape::Sphere { .. } => normal.dot(direction).max(0.0) / PI
+        }
+    }
+
+    fn radiance(&self, normal: &Vector3, direction: &Vector3) -> Color {
+        match self.shape {
+            Shape::Sphere { ref radiance, .. } if normal.dot(direction) > 0.0 => radiance.clone(),
+            _ => Color::black()
+        }
+    }
+}
+
+impl<'a> Vertex<'a> {
+    fn is_on_surface(&self) -> bool {
+        self.normal.norm() > 0.0
+    }
+
+    // Paths can be connected through the vertex, specular only materials can't be connected
+    fn is_connectible(&self) -> bool {
+        match self.kind {
+            Kind::Camera | Kind::Light => true,
+            Kind::Surface { material, ref coords } => material.diffuse_probability(coords) > 0.0
+        }
+    }
+
+    // BRDF for light coming from one vertex and leaving towards other
+    fn f(&self, from: &Point, to: &Point) -> Color {
+        match self.kind {
+            Kind::Surface { material, ref coords } => {
+                let incoming = from.subtract(&self.point);
+                let outgoing = to.subtract(&self.point);
+
+                if self.normal.dot(&incoming) > 0.0 && self.normal.dot(&outgoing) > 0.0 {
+                    material.diffuse_brdf(coords)
+                } else {
+                    Color::black()
+                }
+            },
+            _ => Color::black()
+        }
+    }
+
+    fn cosine(&self, point: &Point) -> f64 {
+        if self.is_on_surface() {
+            self.normal.dot(&point.subtract(&self.point).normalize()).abs()
+        } else {
+            1.0
+        }
+    }
+
+    // Converts solid angle density of picking direction towards next vertex to area density
+    fn to_area(&self, pdf: f64, next: &Vertex) -> f64 {
+        let distance2 = next.point.subtract(&self.point).norm();
+
+        if distance2 == 0.0 {
+            return 0.0;
+        }
+
+        pdf * next.cosine(&self.point) / distance2
+    }
+
+    // Area density of next vertex being picked from this one
+    fn pdf(&self, scene: &Scene, emitters: &[Emitter], next: &Vertex) -> f64 {
+        let direction = next.point.subtract(&self.point).normalize();
+
+        let pdf = match self.kind {
+            Kind::Camera => scene.camera.pdf(&direction, scene.width, scene.height),
+            Kind::Light => match self.emitter {
+                Some(i) => emitters[i].pdf_direction(&self.normal, &direction),
+                None => 0.0
+            },
+            Kind::Surface { material, ref coords } => material.diffuse_pdf(&self.normal, &direction, coords)
+        };
+
+        self.to_area(pdf, next)
+    }
+
+    // Area density of light subpath leaving this vertex on emitter towards next one
+    fn pdf_light(&self, emitters: &[Emitter], next: &Vertex) -> f64 {
+        match self.emitter {
+            Some(i) => {
+                let direction = next.point.subtract(&self.point).normalize();
+                self.to_area(emitters[i].pdf_direction(&self.normal, &direction), next)
+            },
+            None => 0.0
+        }
+    }
+
+    // Area density of light subpath starting at this vertex
+    fn pdf_light_origin(&self, emitters: &[Emitter]) -> f64 {
+        match self.emitter {
+            Some(i) => emitters[i].pdf_position() / emitters.len() as f64,
+            None => 0.0
+        }
+    }
+
+    fn is_delta_light(&self, emitters: &[Emitter]) -> bool {
+        self.emitter.is_some_and(|i| emitters[i].is_delta())
+    }
+}
+
+// Camera subpath vertices and light of the background seen by the path
+fn camera_subpath<'a>(scene: &'a Scene, emitters: &[Emitter<'a>], ray: &Ray, max_vertices: usize,
+                      sampler: &mut Sampler) -> (Vec<Vertex<'a>>, Color) {
+    let direction = ray.direction.normalize();
+    let camera = Vertex {
+        kind: Kind::Camera,
+        point: ray.origin.clone(),
+        normal: Vector3::zero(),
+        emitter: None,
+        beta: Color::white(),
+        delta: false,
+        pdf_fwd: 0.0,
+        pdf_rev: 0.0,
+    };
+
+    let pdf = scene.camera.pdf(&direction, scene.width, scene.height);
+
+    random_walk(scene, emitters, camera, Color::white(), Ray { origin: ray.origin.clone(), direction }, pdf, max_vertices, true, sampler)
+}
+
+fn light_subpath<'a>(scene: &'a Scene, emitters: &[Emitter<'a>], max_vertices: usize, sampler: &mut Sampler) -> Vec<Vertex<'a>> {
+    if emitters.is_empty() {
+        return Vec::new();
+    }
+
+    let index = sampler.below(emitters.len());
+    let pick_pdf = 1.0 / emitters.len() as f64;
+    let emission = emitters[index].sample_emission(sampler);
+
+    if emission.pdf_direction == 0.0 {
+        return Vec::new();
+    }
+
+    let light = Vertex {
+        kind: Kind::Light,
+        point: emission.point.clone(),
+        normal: emission.normal.clone(),
+        emitter: Some(index),
+        beta: emission.radiance.clone(),
+        delta: false,
+        pdf_fwd: emission.pdf_position * pick_pdf,
+        pdf_rev: 0.0,
+    };
+
+    let cosine = if light.is_on_surface() { emission.normal.dot(&emission.direction).abs() } else { 1.0 };
+    let beta = emission.radiance.multiply(cosine / (pick_pdf * emission.pdf_position * emission.pdf_direction));
+    let ray = Ray { origin: emission.point, direction: emission.direction };
+
+    random_walk(scene, emitters, light, beta, ray, emission.pdf_direction, max_vertices, false, sampler).0
+}
+
+// Extends the path from its first vertex along the ray leaving it with given throughput and density.
+// Camera paths end on area lights they hit and collect the background when they escape
+#[allow(clippy::too_many_arguments)]
+fn random_walk<'a>(scene: &'a Scene, emitters: &[Emitter<'a>], start: Vertex<'a>, beta: Color, ray: Ray, pdf: f64,
+                   max_vertices: usize, from_camera: bool, sampler: &mut Sampler) -> (Vec<Vertex<'a>>, Color) {
+    let mut path = vec![start];
+    let mut beta = beta;
+    let mut ray = ray;
+    let mut pdf_fwd = pdf;
+    let mut escaped = Color::black();
+
+    while path.len() < max_vertices {
+        let intersection = scene.trace(&ray);
+        let max_distance = intersection.as_ref().map_or(f64::INFINITY, |i| i.distance);
+
+        if from_camera {
+            if let Some((distance, light)) = integrator::nearest_light(scene, &ray) {
+                if distance < max_distance {
+                    let point = ray.origin.add(&ray.direction.multiply(distance));
+                    let emitter = emitters.iter().position(|e| e.light.is_some_and(|l| ptr::eq(l, light)));
+                    let normal = emitter.map_or(Vector3::zero(), |i| emitters[i].normal_at(&point));
+
+                    let mut vertex = Vertex { kind: Kind::Light, point, normal, emitter, beta, delta: false, pdf_fwd: 0.0, pdf_rev: 0.0 };
+                    vertex.pdf_fwd = path.last().unwrap().to_area(pdf_fwd, &vertex);
+                    path.push(vertex);
+                    break;
+                }
+            }
+        }
+
+        let intersection = match intersection {
+            Some(intersection) => intersection,
+            None => {
+                if from_camera {
+                    escaped = beta.multiply_color(&scene.background(&ray.direction));
+                }
+                break;
+            }
+        };
+
+        let object = intersection.object;
+        let material = object.material();
+        let surface = scene.surface(&ray, &intersection);
+        let emitter = emitters.iter().position(|e| e.object.is_some_and(|o| ptr::eq(o, object)));
+
+        let mut vertex = Vertex {
+            kind: Kind::Surface { material, coords: surface.coords.clone() },
+            point: surface.point.clone(),
+            normal: surface.normal.clone(),
+            emitter,
+            beta: beta.clone(),
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        };
+
+        vertex.pdf_fwd = path.last().unwrap().to_area(pdf_fwd, &vertex);
+
+        if path.len() + 1 >= max_vertices {
+            path.push(vertex);
+            break;
+        }
+
+        let scatter = material.scatter(&ray.direction, &surface.normal, &surface.coords, sampler);
+        let direction = scatter.direction.normalize();
+        let mut pdf_rev = material.diffuse_pdf(&surface.normal, &ray.direction.neg(), &surface.coords);
+
+        pdf_fwd = scatter.pdf;
+
+        if scatter.behavior != RayBehavior::Diffuse {
+            vertex.delta = true;
+            pdf_fwd = 0.0;
+            pdf_rev = 0.0;
+        }
+
+        beta = beta.multiply_color(&scatter.attenuation);
+
+        let previous = path.len() - 1;
+        path[previous].pdf_rev = vertex.to_area(pdf_rev, &path[previous]);
+        path.push(vertex);
+
+        if beta.is_black() {
+            break;
+        }
+
+        ray = Ray { origin: surface.point, direction };
+    }
+
+    (path, escaped)
+}
+
+// Directional lights can't start light subpaths, they are only sampled from camera vertices.
+// Like in Path integrator, vertices past max_depth bounces aren't lit
+fn directional_light(scene: &Scene, camera_path: &[Vertex], max_depth: usize, sampler: &mut Sampler) -> Color {
+    let mut color = Color::black();
+
+    for (i, vertex) in camera_path.iter().enumerate().skip(1).take(max_depth) {
+        if !vertex.is_connectible() {
+            continue;
+        }
+
+        for light in scene.lights.iter().filter(|l| matches!(**l, Light::DirectionalLight(_))) {
+            let sample = match light.sample(&vertex.point, sampler) {
+                Some(sample) => sample,
+                None => continue
+            };
+
+            let to = vertex.point.add(&sample.direction);
+            let contribution = vertex.f(&camera_path[i - 1].point, &to)
+                .multiply_color(&sample.radiance)
+                .multiply_color(&vertex.beta)
+                .multiply(vertex.cosine(&to));
+
+            if contribution.is_black() || (light.cast_shadow() && !integrator::visible(scene, &vertex.point, &sample)) {
+                continue;
+            }
+
+            color = color.add_color(&contribution);
+        }
+    }
+
+    color
+}
+
+// Connects s light vertices with t camera vertices, t is at least 2
+fn connect(scene: &Scene, emitters: &[Emitter], light_path: &[Vertex], camera_path: &[Vertex], s: usize, t: usize,
+           sampler: &mut Sampler) -> Color {
+    let pt = &camera_path[t - 1];
+
+    if s == 0 {
+        // camera subpath hit a light by itself
+        // Emissive objects other than spheres can't start light subpaths or be sampled,
+        // so hitting them is the only strategy which finds their light
+        let emitter = match (pt.emitter, &pt.kind) {
+            (Some(i), _) => &emitters[i],
+            (None, &Kind::Surface { material, .. }) => return pt.beta.multiply_color(&material.emission),
+            _ => return Color::black()
+        };
+
+        let direction = camera_path[t - 2].point.subtract(&pt.point).normalize();
+        let color = pt.beta.multiply_color(&emitter.radiance(&pt.normal, &direction));
+
+        if color.is_black() {
+            return color;
+        }
+
+        return color.multiply(mis_weight(scene, emitters, light_path, camera_path, None, s, t));
+    }
+
+    if let Kind::Light = pt.kind {
+        return Color::black();
+    }
+
+    if !pt.is_connectible() {
+        return Color::black();
+    }
+
+    if s == 1 {
+        // next event estimation, a new point on a random light is picked
+        let index = sampler.below(emitters.len());
+        let pick_pdf = 1.0 / emitters.len() as f64;
+
+        let (sample, mut light) = match emitters[index].sample_incoming(&pt.point, sampler) {
+            Some(sample) => sample,
+            None => return Color::black()
+        };
+
+        light.emitter = Some(index);
+        light.beta = sample.radiance.divide(sample.pdf * pick_pdf);
+        light.pdf_fwd = light.pdf_light_origin(emitters);
+
+        let color = pt.beta
+            .multiply_color(&pt.f(&camera_path[t - 2].point, &light.point))
+            .multiply_color(&light.beta)
+            .multiply(pt.cosine(&light.point));
+
+        if color.is_black() || !integrator::visible(scene, &pt.point, &sample) {
+            return Color::black();
+        }
+
+        return color.multiply(mis_weight(scene, emitters, light_path, camera_path, Some(light), s, t));
+    }
+
+    let qs = &light_path[s - 1];
+
+    if !qs.is_connectible() {
+        return Color::black();
+    }
+
+    let color = qs.beta
+        .multiply_color(&qs.f(&light_path[s - 2].point, &pt.point))
+        .multiply_color(&pt.f(&qs.point, &camera_path[t - 2].point))
+        .multiply_color(&pt.beta)
+        .multiply(geometry_term(qs, pt));
+
+    if color.is_black() || !mutually_visible(scene, &qs.point, &pt.point) {
+        return Color::black();
+    }
+
+    color.multiply(mis_weight(scene, emitters, light_path, camera_path, None, s, t))
+}
+
+// Connects s light vertices directly to the camera. Returns pixel hit and light arriving there
+fn connect_to_camera(scene: &Scene, emitters: &[Emitter], light_path: &[Vertex], camera_path: &[Vertex], s: usize) -> Option<(f64, f64, Color)> {
+    let qs = &light_path[s - 1];
+
+    if !qs.is_connectible() {
+        return None;
+    }
+
+    let camera = &scene.camera;
+    let (x, y) = camera.project(&qs.point, scene.width, scene.height)?;
+
+    let direction = qs.point.subtract(&camera.position);
+    let distance2 = direction.norm();
+    let direction = direction.normalize();
+    let importance = camera.importance(&direction, scene.width, scene.height);
+
+    // Pinhole camera has single position, so density of the camera vertex turns into inverse
+    // square falloff of the connection
+    let cosine = -direction.z;
+    let mut sampled = camera_path[0].clone();
+    sampled.beta = Color::white().multiply(importance * cosine / distance2);
+
+    let color = qs.beta
+        .multiply_color(&qs.f(&light_path[s - 2].point, &camera.position))
+        .multiply_color(&sampled.beta)
+        .multiply(qs.cosine(&camera.position));
+
+    if color.is_black() || !mutually_visible(scene, &qs.point, &camera.position) {
+        return None;
+    }
+
+    let weight = mis_weight(scene, emitters, light_path, camera_path, Some(sampled), s, 1);
+
+    Some((x, y, color.multiply(weight)))
+}
+
+fn geometry_term(a: &Vertex, b: &Vertex) -> f64 {
+    let distance2 = a.point.subtract(&b.point).norm();
+
+    if distance2 == 0.0 { 0.0 } else { a.cosine(&b.point) * b.cosine(&a.point) / distance2 }
+}
+
+fn mutually_visible(scene: &Scene, from: &Point, to: &Point) -> bool {
+    let offset = to.subtract(from);
+    let distance = offset.magnitude();
+    let sample = LightSample { direction: offset.normalize(), distance, radiance: Color::black(), pdf: 0.0 };
+
+    integrator::visible(scene, from, &sample)
+}
+
+// Power heuristic weight of the strategy among all (s, t) splits giving the same path.
+// Densities of vertices around the connection are recomputed as if the path was built
+// by other strategies, zero densities of specular vertices are skipped
+fn mis_weight(scene: &Scene, emitters: &[Emitter], light_path: &[Vertex], camera_path: &[Vertex],
+              sampled: Option<Vertex>, s: usize, t: usize) -> f64 {
+    if s + t == 2 {
+        return 1.0;
+    }
+
+    let mut light: Vec<Vertex> = light_path[..s].to_vec();
+    let mut camera: Vec<Vertex> = camera_path[..t].to_vec();
+
+    if let Some(sampled) = sampled {
+        if s == 1 {
+            light[0] = sampled;
+        } else if t == 1 {
+            camera[0] = sampled;
+        }
+    }
+
+    let pt_rev = if s > 0 {
+        light[s - 1].pdf(scene, emitters, &camera[t - 1])
+    } else {
+        camera[t - 1].pdf_light_origin(emitters)
+    };
+
+    let pt_minus_rev = if t > 1 {
+        Some(if s > 0 {
+            camera[t - 1].pdf(scene, emitters, &camera[t - 2])
+        } else {
+            camera[t - 1].pdf_light(emitters, &camera[t - 2])
+        })
+    } else {
+        None
+    };
+
+    let qs_rev = if s > 0 { Some(camera[t - 1].pdf(scene, emitters, &light[s - 1])) } else { None };
+    let qs_minus_rev = if s > 1 { Some(light[s - 1].pdf(scene, emitters, &light[s - 2])) } else { None };
+
+    camera[t - 1].pdf_rev = pt_rev;
+    camera[t - 1].delta = false;
+
+    if let Some(pdf) = pt_minus_rev {
+        camera[t - 2].pdf_rev = pdf;
+    }
+
+    if let Some(pdf) = qs_rev {
+        light[s - 1].pdf_rev = pdf;
+        light[s - 1].delta = false;
+    }
+
+    if let Some(pdf) = qs_minus_rev {
+        light[s - 2].pdf_rev = pdf;
+    }
+
+    let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+    let mut sum = 0.0;
+
+    let mut ratio = 1.0;
+    for i in (1..t).rev() {
+        ratio *= remap(camera[i].pdf_rev) / remap(camera[i].pdf_fwd);
+
+        if !camera[i].delta && !camera[i - 1].delta {
+            sum += ratio * ratio;
+        }
+    }
+
+    let mut ratio = 1.0;
+    for i in (0..s).rev() {
+        ratio *= remap(light[i].pdf_rev) / remap(light[i].pdf_fwd);
+
+        let delta_light = if i > 0 { light[i - 1].delta } else { light[0].is_delta_light(emitters) };
+
+        if !light[i].delta && !delta_light {
+            sum += ratio * ratio;
+        }
+    }
+
+    1.0 / (1.0 + sum)
+}
@@ -1,9 +1,73 @@
-use vector::Point;
+use vector::{Vector3, Point};
+use adaptive::AdaptiveSampling;
+use sampler::SamplerKind;
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Camera {
     pub fov: f64,
     pub samples: u32,
     pub diffuse: u32,
-    pub position: Point
+    pub position: Point,
+
+    // Lets pixels stop before or go past samples depending on their noise
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub adaptive: Option<AdaptiveSampling>,
+
+    // Sequence random numbers of camera samples come from
+    #[serde(default)]
+    pub sampler: SamplerKind,
+
+    // Random numbers of every sample are derived from the seed, pixel and sample index,
+    // so renders of the same scene are identical
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl Camera {
+    // Size of the image plane at distance 1 in front of the camera, see Ray::create_prime
+    fn plane_size(&self, width: u32, height: u32) -> (f64, f64) {
+        let aspect_ratio = width as f64 / height as f64;
+        let fov_adjustment = (self.fov.to_radians() / 2.0).tan();
+
+        (2.0 * aspect_ratio * fov_adjustment, 2.0 * fov_adjustment)
+    }
+
+    // Pixel coordinates of the point, None if it's outside of the image
+    pub fn project(&self, point: &Point, width: u32, height: u32) -> Option<(f64, f64)> {
+        let direction = point.subtract(&self.position);
+
+        if direction.z >= 0.0 {
+            return None;
+        }
+
+        let (plane_width, plane_height) = self.plane_size(width, height);
+        let x = (direction.x / -direction.z / plane_width + 0.5) * width as f64;
+        let y = (0.5 - direction.y / -direction.z / plane_height) * height as f64;
+
+        if x < 0.0 || y < 0.0 || x >= width as f64 || y >= height as f64 {
+            None
+        } else {
+            Some((x, y))
+        }
+    }
+
+    // Solid angle density of camera rays picking normalized direction
+    pub fn pdf(&self, direction: &Vector3, width: u32, height: u32) -> f64 {
+        let cosine = -direction.z;
+
+        if cosine <= 0.0 || self.project(&self.position.add(direction), width, height).is_none() {
+            return 0.0;
+        }
+
+        let (plane_width, plane_height) = self.plane_size(width, height);
+
+        1.0 / (plane_width * plane_height * cosine * cosine * cosine)
+    }
+
+    // Sensitivity of the image to light arriving from direction, normalized over the whole image
+    pub fn importance(&self, direction: &Vector3, width: u32, height: u32) -> f64 {
+        let cosine = -direction.z;
+
+        if cosine <= 0.0 { 0.0 } else { self.pdf(direction, width, height) / cosine }
+    }
 }
@@ -1,6 +1,6 @@
 use image::{Rgba, Pixel};
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Color {
     pub r: f64,
     pub g: f64,
@@ -85,6 +85,31 @@ impl Color {
                             255)
     }
 
+    pub fn gamma_encode(&self) -> Color {
+        Color {
+            r: gamma_encode(self.r),
+            g: gamma_encode(self.g),
+            b: gamma_encode(self.b),
+        }
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.r <= 0.0 && self.g <= 0.0 && self.b <= 0.0
+    }
+
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    // Reads channels as is, without gamma decoding. Used for non-color data like normal maps
+    pub fn from_rgba_linear(rgba: Rgba<u8>) -> Color {
+        Color {
+            r: (rgba.data[0] as f64) / 255.0,
+            g: (rgba.data[1] as f64) / 255.0,
+            b: (rgba.data[2] as f64) / 255.0,
+        }
+    }
+
     pub fn from_rgba(rgba: Rgba<u8>) -> Color {
         Color {
             r: gamma_decode((rgba.data[0] as f64) / 255.0),
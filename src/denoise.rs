@@ -0,0 +1,92 @@
+use color::Color;
+
+// Edge avoiding à-trous wavelet filter. Every iteration blurs the image with 5x5 kernel whose
+// taps are spread twice as far as in the previous one, taps which differ from the center pixel
+// in color, normal or albedo get less weight so edges and texture details stay sharp
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Denoiser {
+    // Number of filter passes, the filter reaches 2^iterations pixels away
+    #[serde(default="Denoiser::default_iterations")]
+    pub iterations: u32,
+    // Differences at which tap weight drops to 1/e. Bigger values remove more noise but blur details
+    #[serde(default="Denoiser::default_color_sigma")]
+    pub color_sigma: f64,
+    #[serde(default="Denoiser::default_normal_sigma")]
+    pub normal_sigma: f64,
+    #[serde(default="Denoiser::default_albedo_sigma")]
+    pub albedo_sigma: f64,
+}
+
+// Guide buffers taken at the first hit of every pixel
+pub struct Guides<'a> {
+    pub albedo: &'a [Color],
+    // Normals mapped to 0..1 like in the normal pass
+    pub normals: &'a [Color],
+}
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+impl Denoiser {
+    fn default_iterations() -> u32 { 5 }
+    fn default_color_sigma() -> f64 { 0.5 }
+    fn default_normal_sigma() -> f64 { 0.1 }
+    fn default_albedo_sigma() -> f64 { 0.1 }
+
+    // Iterations whose taps are no further apart than the image size, later ones are skipped
+    pub fn iteration_count(&self, width: u32, height: u32) -> u32 {
+        self.iterations.min(32 - width.max(height).leading_zeros())
+    }
+
+    // Filters linear colors before they are clamped
+    pub fn denoise(&self, colors: &[Color], guides: &Guides, width: u32, height: u32) -> Vec<Color> {
+        let mut current = colors.to_vec();
+        let mut color_sigma = self.color_sigma;
+
+        for iteration in 0..self.iteration_count(width, height) {
+            current = self.filter(&current, guides, width as i64, height as i64, 1 << iteration, color_sigma);
+
+            // Noise is lower after every pass, so colors have to be closer to count as the same
+            color_sigma /= 2.0;
+        }
+
+        current
+    }
+
+    fn filter(&self, colors: &[Color], guides: &Guides, width: i64, height: i64, step: i64, color_sigma: f64) -> Vec<Color> {
+        let mut filtered = Vec::with_capacity(colors.len());
+
+        for y in 0..height {
+            for x in 0..width {
+                let center = (y * width + x) as usize;
+                let mut sum = Color::black();
+                let mut total_weight = 0.0;
+
+                for (ky, wy) in KERNEL.iter().enumerate() {
+                    for (kx, wx) in KERNEL.iter().enumerate() {
+                        let sx = (x + (kx as i64 - 2) * step).max(0).min(width - 1);
+                        let sy = (y + (ky as i64 - 2) * step).max(0).min(height - 1);
+                        let tap = (sy * width + sx) as usize;
+
+                        let weight = wx * wy
+                            * edge_weight(&colors[center], &colors[tap], color_sigma)
+                            * edge_weight(&guides.normals[center], &guides.normals[tap], self.normal_sigma)
+                            * edge_weight(&guides.albedo[center], &guides.albedo[tap], self.albedo_sigma);
+
+                        sum = sum.add_color(&colors[tap].multiply(weight));
+                        total_weight += weight;
+                    }
+                }
+
+                filtered.push(if total_weight > 0.0 { sum.divide(total_weight) } else { colors[center].clone() });
+            }
+        }
+
+        filtered
+    }
+}
+
+fn edge_weight(a: &Color, b: &Color, sigma: f64) -> f64 {
+    let distance2 = (a.r - b.r).powi(2) + (a.g - b.g).powi(2) + (a.b - b.b).powi(2);
+
+    (-distance2 / (sigma * sigma)).exp()
+}
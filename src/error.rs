@@ -0,0 +1,111 @@
+use image::ImageError;
+use serde_json;
+use serde_json::error::Category;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum SceneError {
+    // Scene file can't be read
+    Io(io::Error),
+    // Malformed file, e.g. missing bracket or unexpected end of file
+    Syntax { line: usize, column: usize, message: String },
+    // Object or light of unsupported kind
+    UnknownKind { line: usize, column: usize, message: String },
+    // Well formed file which doesn't match scene structure, e.g. string instead of number
+    Format { line: usize, column: usize, message: String },
+    // Parsed value which can't be rendered, e.g. sphere with zero radius
+    InvalidValue { location: String, message: String },
+    // Texture or other referenced file can't be loaded
+    MissingAsset { path: PathBuf, error: ImageError },
+    // Scene file extension doesn't match any known format
+    UnsupportedFormat(String),
+    // Scene can't be written in requested format
+    Output(String),
+    // Included scene file can't be loaded
+    Include { path: PathBuf, error: Box<SceneError> },
+}
+
+impl SceneError {
+    // Error reported by format parser which doesn't tell syntax and data errors apart
+    pub fn parse_error(line: usize, column: usize, message: &str) -> SceneError {
+        let message = strip_position(message);
+
+        if message.starts_with("unknown variant") {
+            SceneError::UnknownKind { line, column, message }
+        } else {
+            SceneError::Format { line, column, message }
+        }
+    }
+
+    pub fn invalid_value(location: &str, message: &str) -> SceneError {
+        SceneError::InvalidValue {
+            location: location.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneError::Io(ref e) => write!(f, "Unable to read scene file: {}", e),
+            SceneError::Syntax { line, column, ref message } => {
+                write!(f, "Syntax error at line {}, column {}: {}", line, column, message)
+            },
+            SceneError::UnknownKind { line, column, ref message } => {
+                write!(f, "Unknown kind at line {}, column {}: {}", line, column, message)
+            },
+            SceneError::Format { line, column, ref message } => {
+                write!(f, "Invalid scene at line {}, column {}: {}", line, column, message)
+            },
+            SceneError::InvalidValue { ref location, ref message } => {
+                write!(f, "Invalid value of {}: {}", location, message)
+            },
+            SceneError::MissingAsset { ref path, ref error } => {
+                write!(f, "Unable to load {}: {}", path.display(), error)
+            },
+            SceneError::UnsupportedFormat(ref extension) => {
+                write!(f, "Unsupported scene format '{}', use json, yaml, toml or ron", extension)
+            },
+            SceneError::Output(ref message) => write!(f, "Unable to write scene: {}", message),
+            SceneError::Include { ref path, ref error } => write!(f, "In {}: {}", path.display(), error),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(error: io::Error) -> SceneError {
+        SceneError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(error: serde_json::Error) -> SceneError {
+        let line = error.line();
+        let column = error.column();
+
+        let message = strip_position(&error.to_string());
+
+        match error.classify() {
+            Category::Io => SceneError::Io(error.into()),
+            Category::Syntax | Category::Eof => SceneError::Syntax { line, column, message },
+            Category::Data if message.starts_with("unknown variant") => {
+                SceneError::UnknownKind { line, column, message }
+            },
+            Category::Data => SceneError::Format { line, column, message },
+        }
+    }
+}
+
+// Parsers append error position to the message, it's reported separately
+fn strip_position(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message.to_string()
+    }
+}
@@ -0,0 +1,72 @@
+use color::Color;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Light which reaches the camera through arbitrary pixels, e.g. from light tracing. It's shared
+// between all render threads and added to the image once rendering is done
+pub struct Splats {
+    width: u32,
+    height: u32,
+    // Channels are summed as fixed point numbers. Integer addition doesn't depend on the
+    // order threads add splats in, so renders stay reproducible
+    channels: Vec<AtomicU64>,
+}
+
+// Fixed point units per unit of radiance
+const SCALE: f64 = (1u64 << 24) as f64;
+
+impl Splats {
+    pub fn new(width: u32, height: u32) -> Splats {
+        Splats {
+            width,
+            height,
+            channels: (0..width * height * 3).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    // Adds color to the pixel containing image point
+    pub fn add(&self, x: f64, y: f64, color: &Color) {
+        let x = (x as u32).min(self.width - 1);
+        let y = (y as u32).min(self.height - 1);
+        let offset = ((y * self.width + x) * 3) as usize;
+
+        for (channel, value) in [color.r, color.g, color.b].iter().enumerate() {
+            // NaN and infinite radiance come from degenerate paths, they would fill the pixel
+            if !value.is_finite() {
+                continue;
+            }
+
+            // Conversion saturates for huge values and so does the sum, saturated sum doesn't
+            // depend on the order either as all units are positive
+            let units = (value.max(0.0) * SCALE).round() as u64;
+            let _ = self.channels[offset + channel].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| Some(sum.saturating_add(units)));
+        }
+    }
+
+    pub fn into_pixels(self) -> Vec<Color> {
+        self.channels.chunks(3)
+            .map(|pixel| Color {
+                r: pixel[0].load(Ordering::Relaxed) as f64 / SCALE,
+                g: pixel[1].load(Ordering::Relaxed) as f64 / SCALE,
+                b: pixel[2].load(Ordering::Relaxed) as f64 / SCALE,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_non_finite_and_saturates() {
+        let splats = Splats::new(2, 1);
+        splats.add(0.5, 0.5, &Color { r: 1.5, g: f64::NAN, b: -1.0 });
+        splats.add(0.5, 0.5, &Color { r: 0.25, g: f64::INFINITY, b: 2.0 });
+        splats.add(1.5, 0.5, &Color { r: 1e300, g: 0.0, b: 0.0 });
+        splats.add(1.5, 0.5, &Color { r: 1e300, g: 0.0, b: 0.0 });
+
+        let pixels = splats.into_pixels();
+        assert_eq!((pixels[0].r, pixels[0].g, pixels[0].b), (1.75, 0.0, 2.0));
+        assert_eq!(pixels[1].r, u64::MAX as f64 / SCALE);
+    }
+}
@@ -0,0 +1,83 @@
+use error::SceneError;
+use scene::Scene;
+use serde::de::DeserializeOwned;
+use ron;
+use serde_json;
+use serde_yaml;
+use toml;
+use std::path::Path;
+
+// Scene file formats. All of them share the same serde model, so any scene
+// written in one format can be converted to any other
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneFormat {
+    Json,
+    Yaml,
+    Toml,
+    Ron
+}
+
+impl SceneFormat {
+    pub fn from_path(path: &Path) -> Result<SceneFormat, SceneError> {
+        let extension = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "json" => Ok(SceneFormat::Json),
+            "yaml" | "yml" => Ok(SceneFormat::Yaml),
+            "toml" => Ok(SceneFormat::Toml),
+            "ron" => Ok(SceneFormat::Ron),
+            _ => Err(SceneError::UnsupportedFormat(extension))
+        }
+    }
+
+    // Parses scene or any part of it, e.g. included fragment
+    pub fn parse<T: DeserializeOwned>(&self, contents: &str) -> Result<T, SceneError> {
+        match *self {
+            SceneFormat::Json => Ok(serde_json::from_str(contents)?),
+            SceneFormat::Yaml => {
+                serde_yaml::from_str(contents).map_err(|e| {
+                    let (line, column) = e.location().map_or((0, 0), |l| (l.line(), l.column()));
+
+                    SceneError::parse_error(line, column, &e.to_string())
+                })
+            },
+            SceneFormat::Toml => {
+                toml::from_str(contents).map_err(|e| {
+                    let (line, column) = e.span().map_or((0, 0), |span| line_column(contents, span.start));
+
+                    SceneError::parse_error(line, column, e.message())
+                })
+            },
+            SceneFormat::Ron => {
+                ron::from_str(contents).map_err(|e| {
+                    SceneError::parse_error(e.position.line, e.position.col, &e.code.to_string())
+                })
+            }
+        }
+    }
+
+    pub fn write(&self, scene: &Scene) -> Result<String, SceneError> {
+        let output = match *self {
+            SceneFormat::Json => serde_json::to_string_pretty(scene).map_err(|e| e.to_string()),
+            SceneFormat::Yaml => serde_yaml::to_string(scene).map_err(|e| e.to_string()),
+            SceneFormat::Toml => toml::to_string_pretty(scene).map_err(|e| e.to_string()),
+            SceneFormat::Ron => {
+                ron::ser::to_string_pretty(scene, ron::ser::PrettyConfig::default()).map_err(|e| e.to_string())
+            }
+        };
+
+        output.map_err(SceneError::Output)
+    }
+}
+
+// One based line and column of byte offset
+fn line_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+
+    (line, column)
+}
@@ -1,28 +1,29 @@
 use material::Material;
+use texture::Texture;
 use vector::{Vector2, Vector3, Point};
 use ray::Ray;
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub enum Object {
     Sphere(Sphere),
     Plane(Plane)
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Sphere {
     pub center: Point,
     pub radius: f64,
 
-    #[serde(default)]
+    #[serde(default, with="::material::reference")]
     pub material: Material,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Plane {
     pub origin: Point,
     pub normal: Vector3,
 
-    #[serde(default)]
+    #[serde(default, with="::material::reference")]
     pub material: Material,
 }
 
@@ -30,6 +31,9 @@ pub trait Intersectable {
     fn intersect(&self, ray: &Ray) -> Option<f64>;
     fn surface_normal(&self, point: &Point) -> Vector3;
     fn texture_coords(&self, point: &Point) -> Vector2;
+    fn object_coords(&self, point: &Point) -> Point;
+    fn tangent(&self, point: &Point) -> Vector3;
+    fn bitangent(&self, point: &Point) -> Vector3;
 }
 
 impl Object {
@@ -39,6 +43,27 @@ impl Object {
             Object::Sphere(ref s) => &s.material,
        }
     }
+
+    // Editing the material detaches it from the scene material it was copied from,
+    // so the object is saved with its own copy and the edits aren't lost
+    pub fn material_mut(&mut self) -> &mut Material {
+        let material = self.shared_material_mut();
+        material.name = None;
+
+        material
+    }
+
+    // Loading texture images keeps the material saved by name
+    pub fn textures_mut(&mut self) -> Vec<&mut Texture> {
+        self.shared_material_mut().textures_mut()
+    }
+
+    fn shared_material_mut(&mut self) -> &mut Material {
+        match *self {
+            Object::Plane(ref mut p) => &mut p.material,
+            Object::Sphere(ref mut s) => &mut s.material,
+       }
+    }
 }
 
 impl Intersectable for Object {
@@ -62,6 +87,27 @@ impl Intersectable for Object {
             Object::Sphere(ref s) => s.texture_coords(point)
        }
     }
+
+    fn object_coords(&self, point: &Point) -> Point {
+        match *self {
+            Object::Plane(ref p) => p.object_coords(point),
+            Object::Sphere(ref s) => s.object_coords(point)
+       }
+    }
+
+    fn tangent(&self, point: &Point) -> Vector3 {
+        match *self {
+            Object::Plane(ref p) => p.tangent(point),
+            Object::Sphere(ref s) => s.tangent(point)
+       }
+    }
+
+    fn bitangent(&self, point: &Point) -> Vector3 {
+        match *self {
+            Object::Plane(ref p) => p.bitangent(point),
+            Object::Sphere(ref s) => s.bitangent(point)
+       }
+    }
 }
 
 impl Intersectable for Plane {
@@ -95,12 +141,7 @@ impl Intersectable for Plane {
     fn texture_coords(&self, point: &Point) -> Vector2 {
         let hit_vec = point.subtract(&self.origin);
 
-        let mut x_axis = self.normal.cross(&Vector3::new(0.0, 0.0, 1.0));
-
-        if x_axis.magnitude() == 0.0 {
-            x_axis = self.normal.cross(&Vector3::new(0.0, 1.0, 0.0));
-        }
-
+        let x_axis = self.x_axis();
         let y_axis = self.normal.cross(&x_axis);
 
         Vector2 {
@@ -108,6 +149,32 @@ impl Intersectable for Plane {
             y: hit_vec.dot(&y_axis)
         }
     }
+
+    fn object_coords(&self, point: &Point) -> Point {
+        point.subtract(&self.origin)
+    }
+
+    fn tangent(&self, _: &Point) -> Vector3 {
+        self.x_axis().normalize()
+    }
+
+    // direction of growing v coordinate, surface normal faces away from the plane normal so
+    // it can't be derived from the shading normal like on spheres
+    fn bitangent(&self, _: &Point) -> Vector3 {
+        self.normal.cross(&self.x_axis()).normalize()
+    }
+}
+
+impl Plane {
+    fn x_axis(&self) -> Vector3 {
+        let x_axis = self.normal.cross(&Vector3::new(0.0, 0.0, 1.0));
+
+        if x_axis.magnitude() == 0.0 {
+            self.normal.cross(&Vector3::new(0.0, 1.0, 0.0))
+        } else {
+            x_axis
+        }
+    }
 }
 
 impl Intersectable for Sphere {
@@ -133,16 +200,13 @@ impl Intersectable for Sphere {
         let d1 = adj + adj3;
         let d2 = adj - adj3;
 
-        if d1 < 0.0 && d2 < 0.0 {
-            return None;
+        // nearer hit is behind the origin when ray starts inside of the sphere
+        if d2 >= 0.001 {
+            Some(d2)
+        } else if d1 >= 0.001 {
+            Some(d1)
         } else {
-            let distance = if d2 > d1 { d1 } else { d2 };
-
-            if distance < 0.001 {
-                return None;
-            } else {
-                Some(distance)
-            }
+            None
         }
     }
 
@@ -158,4 +222,25 @@ impl Intersectable for Sphere {
             y: (hit_vec.y / self.radius).acos()  / ::std::f64::consts::PI,
         }
     }
+
+    fn object_coords(&self, point: &Point) -> Point {
+        point.subtract(&self.center)
+    }
+
+    // direction of growing u coordinate, i.e. around the vertical axis
+    fn tangent(&self, point: &Point) -> Vector3 {
+        let hit_vec = point.subtract(&self.center);
+        let tangent = Vector3::new(-hit_vec.z, 0.0, hit_vec.x);
+
+        if tangent.magnitude() == 0.0 {
+            Vector3::new(1.0, 0.0, 0.0)
+        } else {
+            tangent.normalize()
+        }
+    }
+
+    // direction of growing v coordinate, i.e. down along the meridian
+    fn bitangent(&self, point: &Point) -> Vector3 {
+        self.surface_normal(point).cross(&self.tangent(point))
+    }
 }
@@ -0,0 +1,522 @@
+use bdpt::Bidirectional;
+use color::Color;
+use film::Splats;
+use photon::{PhotonMap, PhotonMapping};
+use whitted::Whitted;
+use geometry::Object;
+use intersection::Surface;
+use light::{Light, LightSample};
+use material::{Material, RayBehavior};
+use ray::Ray;
+use sampling;
+use scene::Scene;
+use vector::Point;
+use sampler::Sampler;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::ser::SerializeMap;
+use serde::de::{self, Visitor, MapAccess, IgnoredAny};
+use std::fmt;
+
+#[derive(Debug, Clone, Default)]
+pub enum Integrator {
+    // Direct light scaled by material albedo plus recursive bounce, depth is set by camera.diffuse
+    #[default]
+    Legacy,
+    // Unidirectional path tracer with next event estimation, material albedo is ignored
+    Path(PathTracer),
+    // Bidirectional path tracer, renders caustics much faster than Path
+    Bidirectional(Bidirectional),
+    // Path tracer which takes caustics from photon maps built before rendering
+    Photon(PhotonMapping),
+    // Clay preview shaded by unoccluded part of the hemisphere, materials and lights are ignored
+    AmbientOcclusion(AmbientOcclusion),
+    // Deterministic recursive ray tracer with hard shadows and highlights, renders without noise
+    Whitted(Whitted),
+}
+
+// State shared by all samples of one render
+pub struct RenderContext {
+    pub splats: Splats,
+    pub photon_maps: Vec<PhotonMap>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PathTracer {
+    // Maximum number of bounces of a path
+    #[serde(default="PathTracer::default_max_depth")]
+    pub max_depth: u32,
+    // Bounce after which paths are randomly terminated by their throughput
+    #[serde(default="PathTracer::default_roulette_depth")]
+    pub roulette_depth: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AmbientOcclusion {
+    // Geometry further than radius from the hit point doesn't occlude it
+    #[serde(default="AmbientOcclusion::default_radius")]
+    pub radius: f64,
+    // Occlusion rays traced for every camera ray
+    #[serde(default="AmbientOcclusion::default_samples")]
+    pub samples: u32,
+}
+
+// Light reaching the camera split by the first bounce of its path. Parts add up to the
+// whole radiance, light of every scene light is also counted separately
+#[derive(Debug, Clone)]
+pub struct Components {
+    // Sky, lights and emissive surfaces seen directly
+    pub emission: Color,
+    // Light reaching the first hit straight from lights, emissive surfaces and sky
+    pub direct_diffuse: Color,
+    pub indirect_diffuse: Color,
+    pub reflection: Color,
+    pub refraction: Color,
+    pub lights: Vec<Color>,
+}
+
+// Distance shadow rays stop short of sampled light surface
+const SHADOW_BIAS: f64 = 1e-4;
+
+impl Integrator {
+    // Runs passes which have to be done before rendering, e.g. photon tracing
+    pub fn prepare(&self, scene: &Scene) -> RenderContext {
+        let photon_maps = match *self {
+            Integrator::Photon(ref photon) => photon.trace_photons(scene),
+            _ => Vec::new()
+        };
+
+        RenderContext {
+            splats: Splats::new(scene.width, scene.height),
+            photon_maps,
+        }
+    }
+
+    // Side of the grid of fixed positions pixels are sampled at, other integrators
+    // sample random positions
+    pub fn pixel_grid(&self) -> Option<u32> {
+        match *self {
+            Integrator::Whitted(ref whitted) => Some(whitted.grid),
+            _ => None
+        }
+    }
+
+    // Radiance split into parts for lighting passes, only Path integrator tracks them
+    pub fn components(&self, scene: &Scene, ray: &Ray, sampler: &mut Sampler) -> Option<Components> {
+        match *self {
+            Integrator::Path(ref path) => Some(path.components(scene, ray, sampler)),
+            _ => None
+        }
+    }
+
+    // Light arriving at the camera along the ray for the current sample of the sampler.
+    // Light reaching other pixels is added to context splats
+    pub fn radiance(&self, scene: &Scene, ray: &Ray, context: &RenderContext, sampler: &mut Sampler) -> Color {
+        match *self {
+            Integrator::Legacy => scene.get_color(ray, scene.camera.diffuse, sampler),
+            Integrator::Path(ref path) => path.radiance(scene, ray, sampler),
+            Integrator::Bidirectional(ref bdpt) => bdpt.radiance(scene, ray, &context.splats, sampler),
+            Integrator::Photon(ref photon) => {
+                let map = &context.photon_maps[sampler.index() as usize % context.photon_maps.len()];
+
+                photon.radiance(scene, ray, map, sampler)
+            },
+            Integrator::AmbientOcclusion(ref ao) => ao.radiance(scene, ray, sampler),
+            Integrator::Whitted(ref whitted) => whitted.radiance(scene, ray),
+        }
+    }
+}
+
+const NAMES: [&str; 6] = ["Legacy", "Path", "Bidirectional", "Photon", "ao", "Whitted"];
+
+// Integrator is written as its name when it has no settings and as a map from the name to
+// settings otherwise. Maps are used in all formats, so RON scenes round trip through the same visitor
+impl Serialize for Integrator {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        if let Integrator::Legacy = *self {
+            return serializer.serialize_str("Legacy");
+        }
+
+        let mut map = serializer.serialize_map(Some(1))?;
+
+        match *self {
+            Integrator::Legacy => {},
+            Integrator::Path(ref path) => map.serialize_entry("Path", path)?,
+            Integrator::Bidirectional(ref bdpt) => map.serialize_entry("Bidirectional", bdpt)?,
+            Integrator::Photon(ref photon) => map.serialize_entry("Photon", photon)?,
+            Integrator::AmbientOcclusion(ref ao) => map.serialize_entry("ao", ao)?,
+            Integrator::Whitted(ref whitted) => map.serialize_entry("Whitted", whitted)?,
+        }
+
+        map.end()
+    }
+}
+
+// Integrator can be given either as a plain name, which uses default settings, or as a map
+// from the name to settings
+impl<'de> Deserialize<'de> for Integrator {
+    fn deserialize<D>(deserializer: D) -> Result<Integrator, D::Error>
+        where D: Deserializer<'de>
+    {
+        struct IntegratorVisitor;
+
+        impl<'de> Visitor<'de> for IntegratorVisitor {
+            type Value = Integrator;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("integrator name or map from integrator name to settings")
+            }
+
+            fn visit_str<E>(self, name: &str) -> Result<Integrator, E>
+                where E: de::Error
+            {
+                match name {
+                    "Legacy" => Ok(Integrator::Legacy),
+                    "Path" => Ok(Integrator::Path(PathTracer::default())),
+                    "Bidirectional" => Ok(Integrator::Bidirectional(Bidirectional::default())),
+                    "Photon" => Ok(Integrator::Photon(PhotonMapping::default())),
+                    "ao" => Ok(Integrator::AmbientOcclusion(AmbientOcclusion::default())),
+                    "Whitted" => Ok(Integrator::Whitted(Whitted::default())),
+                    _ => Err(de::Error::unknown_variant(name, &NAMES))
+                }
+            }
+
+            fn visit_map<M>(self, mut map: M) -> Result<Integrator, M::Error>
+                where M: MapAccess<'de>
+            {
+                let name: String = map.next_key()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+
+                let integrator = match name.as_str() {
+                    "Legacy" => map.next_value::<IgnoredAny>().map(|_| Integrator::Legacy)?,
+                    "Path" => Integrator::Path(map.next_value()?),
+                    "Bidirectional" => Integrator::Bidirectional(map.next_value()?),
+                    "Photon" => Integrator::Photon(map.next_value()?),
+                    "ao" => Integrator::AmbientOcclusion(map.next_value()?),
+                    "Whitted" => Integrator::Whitted(map.next_value()?),
+                    _ => return Err(de::Error::unknown_variant(&name, &NAMES))
+                };
+
+                if map.next_key::<IgnoredAny>()?.is_some() {
+                    return Err(de::Error::custom("integrator map must have exactly one entry"));
+                }
+
+                Ok(integrator)
+            }
+        }
+
+        deserializer.deserialize_any(IntegratorVisitor)
+    }
+}
+
+impl Default for PathTracer {
+    fn default() -> PathTracer {
+        PathTracer { max_depth: PathTracer::default_max_depth(), roulette_depth: PathTracer::default_roulette_depth() }
+    }
+}
+
+impl PathTracer {
+    fn default_max_depth() -> u32 { 8 }
+    fn default_roulette_depth() -> u32 { 3 }
+
+    // Direct light is estimated twice, by sampling light sources and by hitting them with bounced
+    // rays. Both estimates are combined with power heuristic weights
+    pub fn radiance(&self, scene: &Scene, ray: &Ray, sampler: &mut Sampler) -> Color {
+        self.components(scene, ray, sampler).total()
+    }
+
+    // Same as radiance but split by the first bounce of the path
+    pub fn components(&self, scene: &Scene, ray: &Ray, sampler: &mut Sampler) -> Components {
+        let mut components = Components::new(scene.lights.len());
+        let mut throughput = Color::white();
+        let mut ray = Ray { origin: ray.origin.clone(), direction: ray.direction.normalize() };
+
+        // Density of the bounce which produced the ray, zero for camera rays and specular bounces
+        // as light sampling can't pick their directions
+        let mut bounce_pdf = 0.0;
+        let mut first_bounce = None;
+
+        for depth in 0..self.max_depth {
+            let intersection = scene.trace(&ray);
+            let max_distance = intersection.as_ref().map_or(f64::INFINITY, |i| i.distance);
+
+            // Light found by the ray bounced off the first hit is direct, light sampling finds the same light
+            let direct = depth == 1 && first_bounce == Some(RayBehavior::Diffuse);
+
+            if let Some((light_distance, light)) = nearest_light(scene, &ray) {
+                if light_distance < max_distance {
+                    let weight = mis_weight(bounce_pdf, light.pdf(&ray.origin));
+                    let index = scene.lights.iter().position(|l| ::std::ptr::eq(l, light));
+                    components.add(&first_bounce, direct, index, &throughput.multiply_color(&light.radiance()).multiply(weight));
+                    break;
+                }
+            }
+
+            let intersection = match intersection {
+                Some(intersection) => intersection,
+                None => {
+                    components.add(&first_bounce, direct, None, &throughput.multiply_color(&scene.background(&ray.direction)));
+                    break;
+                }
+            };
+
+            let object = intersection.object;
+            let material = object.material();
+            let surface = scene.surface(&ray, &intersection);
+
+            if !material.emission.is_black() {
+                let weight = mis_weight(bounce_pdf, emitter_pdf(object, &ray.origin));
+                components.add(&first_bounce, direct, None, &throughput.multiply_color(&material.emission).multiply(weight));
+            }
+
+            if material.diffuse_probability(&surface.coords) > 0.0 {
+                for_each_light_sample(scene, object, material, &surface, sampler, |index, sample| {
+                    components.add(&first_bounce, first_bounce.is_none(), index, &throughput.multiply_color(sample));
+                });
+            }
+
+            let scatter = material.scatter(&ray.direction, &surface.normal, &surface.coords, sampler);
+            throughput = throughput.multiply_color(&scatter.attenuation);
+            bounce_pdf = if scatter.behavior == RayBehavior::Diffuse { scatter.pdf } else { 0.0 };
+
+            if first_bounce.is_none() {
+                first_bounce = Some(scatter.behavior);
+            }
+
+            // Russian roulette, surviving paths are boosted to keep the estimate unbiased
+            if depth >= self.roulette_depth {
+                let survival = throughput.r.max(throughput.g).max(throughput.b).min(0.95);
+                let rand = sampler.next_1d();
+
+                if survival <= 0.0 || rand > survival {
+                    break;
+                }
+
+                throughput = throughput.divide(survival);
+            }
+
+            ray = Ray { origin: surface.point, direction: scatter.direction.normalize() };
+        }
+
+        components
+    }
+}
+
+impl Components {
+    pub fn new(lights: usize) -> Components {
+        Components {
+            emission: Color::black(),
+            direct_diffuse: Color::black(),
+            indirect_diffuse: Color::black(),
+            reflection: Color::black(),
+            refraction: Color::black(),
+            lights: vec![Color::black(); lights],
+        }
+    }
+
+    // Adds light which reached the camera after given first bounce. Direct light is
+    // light which reached the first hit straight from its source
+    fn add(&mut self, first_bounce: &Option<RayBehavior>, direct: bool, light: Option<usize>, color: &Color) {
+        let part = match *first_bounce {
+            _ if direct => &mut self.direct_diffuse,
+            None => &mut self.emission,
+            Some(RayBehavior::Diffuse) => &mut self.indirect_diffuse,
+            Some(RayBehavior::Reflect) => &mut self.reflection,
+            Some(RayBehavior::Refract) => &mut self.refraction,
+        };
+
+        *part = part.add_color(color);
+
+        if let Some(index) = light {
+            self.lights[index] = self.lights[index].add_color(color);
+        }
+    }
+
+    pub fn total(&self) -> Color {
+        self.emission
+            .add_color(&self.direct_diffuse)
+            .add_color(&self.indirect_diffuse)
+            .add_color(&self.reflection)
+            .add_color(&self.refraction)
+    }
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> AmbientOcclusion {
+        AmbientOcclusion { radius: AmbientOcclusion::default_radius(), samples: AmbientOcclusion::default_samples() }
+    }
+}
+
+impl AmbientOcclusion {
+    fn default_radius() -> f64 { 1.0 }
+    fn default_samples() -> u32 { 1 }
+
+    // White for unoccluded points and the sky, black for points surrounded by geometry
+    pub fn radiance(&self, scene: &Scene, ray: &Ray, sampler: &mut Sampler) -> Color {
+        let intersection = match scene.trace(ray) {
+            Some(intersection) => intersection,
+            None => return Color::white()
+        };
+
+        let surface = scene.surface(ray, &intersection);
+        let normal = if surface.normal.dot(&ray.direction) > 0.0 { surface.normal.neg() } else { surface.normal };
+        let origin = surface.point.add(&normal.multiply(SHADOW_BIAS));
+
+        let samples = self.samples.max(1);
+        let unoccluded = (0..samples)
+            .filter(|_| {
+                let occlusion_ray = Ray { origin: origin.clone(), direction: normal.add(&sampler.direction()).normalize() };
+
+                scene.trace(&occlusion_ray).is_none_or(|i| i.distance > self.radius)
+            })
+            .count();
+
+        Color::white().multiply(unoccluded as f64 / samples as f64)
+    }
+}
+
+// Next event estimation: one sample from every light and emissive sphere
+pub fn sample_lights(scene: &Scene, object: &Object, material: &Material, surface: &Surface, sampler: &mut Sampler) -> Color {
+    let mut color = Color::black();
+
+    for_each_light_sample(scene, object, material, surface, sampler, |_, sample| color = color.add_color(sample));
+
+    color
+}
+
+// Calls visit with contribution of every light sample and index of the light in scene lights.
+// Emissive spheres have no index
+pub fn for_each_light_sample<F>(scene: &Scene, object: &Object, material: &Material, surface: &Surface, sampler: &mut Sampler, mut visit: F)
+    where F: FnMut(Option<usize>, &Color)
+{
+    let light_samples: Vec<_> = scene.lights.iter().enumerate()
+        .filter_map(|(i, light)| light.sample(&surface.point, sampler).map(|s| (s, light.cast_shadow(), light.is_delta(), Some(i))))
+        .collect();
+
+    let emitter_samples: Vec<_> = scene.geometry.iter()
+        .filter(|o| !::std::ptr::eq(*o, object))
+        .filter_map(|o| sample_emitter(o, &surface.point, sampler).map(|s| (s, true, false, None)))
+        .collect();
+
+    for (sample, cast_shadow, delta, index) in light_samples.into_iter().chain(emitter_samples) {
+        let brdf = material.diffuse_brdf_cos(&surface.normal, &sample.direction, &surface.coords);
+
+        if brdf.is_black() || (cast_shadow && !visible(scene, &surface.point, &sample)) {
+            continue;
+        }
+
+        let weight = if delta {
+            1.0
+        } else {
+            sampling::power_heuristic(sample.pdf, material.diffuse_pdf(&surface.normal, &sample.direction, &surface.coords))
+        };
+
+        visit(index, &brdf.multiply_color(&sample.radiance).multiply(weight / sample.pdf));
+    }
+}
+
+// Weight of light found by bounced ray. Light sampling can't produce camera rays and specular
+// bounces, they get full weight
+pub fn mis_weight(bounce_pdf: f64, light_pdf: f64) -> f64 {
+    if bounce_pdf == 0.0 { 1.0 } else { sampling::power_heuristic(bounce_pdf, light_pdf) }
+}
+
+pub fn nearest_light<'a>(scene: &'a Scene, ray: &Ray) -> Option<(f64, &'a Light)> {
+    scene.lights.iter()
+        .filter_map(|light| light.intersect(ray).map(|d| (d, light)))
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+}
+
+// Emissive spheres are sampled like spherical lights. Other emissive objects are only found by bounced rays
+fn sample_emitter(object: &Object, point: &Point, sampler: &mut Sampler) -> Option<LightSample> {
+    match *object {
+        Object::Sphere(ref s) if !s.material.emission.is_black() => {
+            let (direction, pdf) = sampling::sample_sphere(&s.center, s.radius, point, sampler)?;
+            let distance = sampling::intersect_sphere(&s.center, s.radius, point, &direction)?;
+
+            Some(LightSample { direction, distance, radiance: s.material.emission.clone(), pdf })
+        },
+        _ => None
+    }
+}
+
+// Density of sample_emitter picking direction towards the object
+pub fn emitter_pdf(object: &Object, point: &Point) -> f64 {
+    match *object {
+        Object::Sphere(ref s) => sampling::sphere_pdf(&s.center, s.radius, point),
+        _ => 0.0
+    }
+}
+
+pub fn visible(scene: &Scene, point: &Point, sample: &LightSample) -> bool {
+    let shadow_ray = Ray { origin: point.clone(), direction: sample.direction.clone() };
+
+    match scene.trace(&shadow_ray) {
+        Some(intersection) => intersection.distance > sample.distance - SHADOW_BIAS,
+        None => true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use format::SceneFormat;
+    use serde_json;
+
+    #[derive(Serialize, Deserialize)]
+    struct Settings {
+        integrator: Integrator,
+    }
+
+    fn parse(format: SceneFormat, contents: &str) -> Integrator {
+        format.parse::<Settings>(contents).unwrap().integrator
+    }
+
+    #[test]
+    fn bare_name_uses_default_settings() {
+        for name in NAMES.iter().skip(1) {
+            let bare = parse(SceneFormat::Json, &format!("{{ \"integrator\": \"{}\" }}", name));
+            let empty = parse(SceneFormat::Json, &format!("{{ \"integrator\": {{ \"{}\": {{}} }} }}", name));
+
+            assert_eq!(serde_json::to_string(&bare).unwrap(), serde_json::to_string(&empty).unwrap());
+        }
+
+        match parse(SceneFormat::Yaml, "integrator: ao") {
+            Integrator::AmbientOcclusion(ao) => assert_eq!((ao.radius, ao.samples), (1.0, 1)),
+            other => panic!("parsed {:?}", other)
+        }
+    }
+
+    #[test]
+    fn map_keeps_settings() {
+        match parse(SceneFormat::Json, "{ \"integrator\": { \"Path\": { \"max_depth\": 3 } } }") {
+            Integrator::Path(path) => assert_eq!((path.max_depth, path.roulette_depth), (3, 3)),
+            other => panic!("parsed {:?}", other)
+        }
+
+        assert!(SceneFormat::Json.parse::<Settings>("{ \"integrator\": \"Unknown\" }").is_err());
+        assert!(SceneFormat::Json.parse::<Settings>("{ \"integrator\": { \"Path\": {}, \"ao\": {} } }").is_err());
+    }
+
+    #[test]
+    fn round_trips_in_all_formats() {
+        let formats = [SceneFormat::Json, SceneFormat::Yaml, SceneFormat::Toml, SceneFormat::Ron];
+
+        for name in NAMES.iter() {
+            let integrator = parse(SceneFormat::Json, &format!("{{ \"integrator\": \"{}\" }}", name));
+            let expected = serde_json::to_string(&integrator).unwrap();
+
+            for format in formats.iter() {
+                let written = match *format {
+                    SceneFormat::Json => serde_json::to_string(&Settings { integrator: integrator.clone() }).unwrap(),
+                    SceneFormat::Yaml => ::serde_yaml::to_string(&Settings { integrator: integrator.clone() }).unwrap(),
+                    SceneFormat::Toml => ::toml::to_string(&Settings { integrator: integrator.clone() }).unwrap(),
+                    SceneFormat::Ron => ::ron::to_string(&Settings { integrator: integrator.clone() }).unwrap(),
+                };
+
+                let parsed = parse(format.clone(), &written);
+                assert_eq!(serde_json::to_string(&parsed).unwrap(), expected, "{:?}: {}", format, written);
+            }
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use geometry::Object;
+use texture::TextureCoords;
+use vector::{Vector3, Point};
 
 #[derive(Debug)]
 pub struct Intersection<'a> {
@@ -6,6 +8,14 @@ pub struct Intersection<'a> {
     pub object: &'a Object,
 }
 
+// Shading information at the hit point. Normal is already perturbed by normal and bump maps
+#[derive(Debug)]
+pub struct Surface {
+    pub point: Point,
+    pub normal: Vector3,
+    pub coords: TextureCoords,
+}
+
 impl<'a> Intersection<'a> {
     pub fn new(distance: f64, object: &Object) -> Intersection {
         Intersection {
@@ -1,65 +1,244 @@
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate toml;
+extern crate ron;
 extern crate scoped_threadpool;
 extern crate image;
-extern crate rand;
 extern crate num_cpus;
 
 pub mod scene;
+pub mod error;
+pub mod format;
+pub mod preprocess;
+pub mod lint;
+pub mod integrator;
+pub mod sampling;
+pub mod bdpt;
+pub mod film;
+pub mod photon;
+pub mod whitted;
+pub mod aov;
+pub mod denoise;
+pub mod adaptive;
+pub mod tiles;
+pub mod sampler;
 pub mod geometry;
 pub mod point;
 pub mod vector;
 pub mod color;
 pub mod material;
+pub mod texture;
+pub mod procedural;
+pub mod noise;
 pub mod ray;
 pub mod intersection;
 pub mod light;
 pub mod camera;
 
-use rand::prelude::*;
 use scoped_threadpool::Pool;
 use scene::Scene;
 use image::*;
 use ray::Ray;
 use color::Color;
+use aov::{Pass, PassImage};
+use adaptive::PixelStats;
+use sampler::Sampler;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
 
 pub fn render(scene: &Scene) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-    let mut image = ImageBuffer::new(scene.width, scene.height);
+    render_with_passes(scene, &[]).0
+}
+
+// Renders the image together with auxiliary passes for compositing
+pub fn render_with_passes(scene: &Scene, passes: &[Pass]) -> (ImageBuffer<Rgba<u8>, Vec<u8>>, Vec<PassImage>) {
+    let mut pixels = vec![Pixel { color: Color::black(), samples: 0, values: Vec::new() }; (scene.width * scene.height) as usize];
+    let material_ids = aov::material_ids(scene);
+    let lighting = passes.iter().any(|pass| pass.is_lighting());
+
+    // Denoiser needs albedo and normals even if they weren't requested
+    let mut traced_passes = passes.to_vec();
+
+    if scene.denoiser.is_some() {
+        for guide in [Pass::Albedo, Pass::Normal].iter() {
+            if !traced_passes.contains(guide) {
+                traced_passes.push(*guide);
+            }
+        }
+    }
+
     let threads_count = num_cpus::get() as u32;
     let mut pool = Pool::new(threads_count);
     let pixels_count = scene.width * scene.height;
     println!("Using {} threads", threads_count);
-    print!("Progress: 0%");
 
     let start_time = std::time::Instant::now();
+    let context = scene.integrator.prepare(scene);
+
+    // Integrators with fixed sub-pixel positions replace camera samples with a grid of them,
+    // such pixels are never sampled adaptively
+    let grid = scene.integrator.pixel_grid();
+    let samples = grid.map_or(scene.camera.samples, |grid| grid * grid);
+    let adaptive = scene.camera.adaptive.as_ref().filter(|_| grid.is_none());
+    let max_samples = adaptive.and_then(|adaptive| adaptive.max_samples).unwrap_or(samples);
+
+    let render_pixel = |x: u32, y: u32| {
+        let i = (y * scene.width + x) as usize;
+
+        // Numbers depend only on the seed and the pixel, not on the tile or thread rendering it
+        let mut sampler = Sampler::new(scene.camera.sampler, sampler::derive_seed(scene.camera.seed, i as u64), max_samples);
+        let mut color = Color::black();
+        let mut stats = PixelStats::default();
+        let mut lighting_sums = vec![Color::black(); traced_passes.len()];
+
+        for n in 0..max_samples {
+            sampler.start_sample(n);
+
+            let (rx, ry) = match grid {
+                Some(grid) => (((n % grid) as f64 + 0.5) / grid as f64, ((n / grid) as f64 + 0.5) / grid as f64),
+                None => sampler.next_2d()
+            };
+
+            let ray = Ray::create_prime(rx + x as f64, ry + y as f64, scene);
+
+            let components = if lighting { scene.integrator.components(scene, &ray, &mut sampler) } else { None };
+
+            let radiance = match components {
+                Some(components) => {
+                    for (sum, pass) in lighting_sums.iter_mut().zip(&traced_passes) {
+                        if let Some(value) = pass.lighting_value(&components) {
+                            *sum = sum.add_color(&value);
+                        }
+                    }
+
+                    components.total()
+                },
+                None => scene.integrator.radiance(scene, &ray, &context, &mut sampler)
+            };
+
+            color = color.add_color(&radiance);
+            stats.add(radiance.luminance());
+
+            if adaptive.is_some_and(|adaptive| adaptive.converged(&stats)) {
+                break;
+            }
+        }
+
+        let mut values = Vec::new();
+
+        if !traced_passes.is_empty() {
+            let ray = Ray::create_prime(x as f64 + 0.5, y as f64 + 0.5, scene);
+
+            values = aov::values(scene, &ray, &traced_passes, &material_ids).into_iter()
+                .zip(lighting_sums)
+                .zip(&traced_passes)
+                .map(|((value, sum), pass)| match *pass {
+                    Pass::Samples => Color::white().multiply(stats.count as f64),
+                    _ if pass.is_lighting() => sum.divide(stats.count as f64),
+                    _ => value
+                })
+                .collect();
+        }
+
+        Pixel { color, samples: stats.count, values }
+    };
+
+    let tiles = scene.tiles.split(scene.width, scene.height);
+    let next_tile = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    print!("Progress: 0%");
 
     pool.scoped(|scope| {
-        for (i, (x, y, pixel)) in image.enumerate_pixels_mut().enumerate() {
+        let render_pixel = &render_pixel;
+        let tiles = &tiles;
+        let next_tile = &next_tile;
+
+        // Every worker takes the next tile from the queue until all of them are taken
+        for _ in 0..threads_count {
+            let sender = sender.clone();
+
             scope.execute(move || {
-                let mut rng = thread_rng();
-                let mut color = Color::black();
+                while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                    let buffer: Vec<Pixel> = (0..tile.pixels_count())
+                        .map(|i| render_pixel(tile.x + i % tile.width, tile.y + i / tile.width))
+                        .collect();
+
+                    sender.send((*tile, buffer)).unwrap();
+                }
+            });
+        }
 
-                let progress = ((i as f64 / pixels_count as f64) * 100.0) as u8;
+        // Only workers hold senders now, so the loop below ends if any of them dies
+        drop(sender);
 
-                print!("\rProgress: {}%", progress);
+        let mut done = 0;
+        let mut progress = 0;
 
-                for _n in 0..scene.camera.samples {
-                    let rx: f64 = rng.gen();
-                    let ry: f64 = rng.gen();
-                    let ray = Ray::create_prime(rx + x as f64, ry + y as f64, scene);
+        // Finished tiles are copied into the image as they come, progress is printed when it changes
+        for (tile, buffer) in receiver.iter().take(tiles.len()) {
+            for (i, pixel) in buffer.into_iter().enumerate() {
+                let x = tile.x + i as u32 % tile.width;
+                let y = tile.y + i as u32 / tile.width;
 
+                pixels[(y * scene.width + x) as usize] = pixel;
+            }
 
-                    color = color.add_color(&scene.get_color(&ray, scene.camera.diffuse));
-                }
+            done += tile.pixels_count();
 
-                *pixel = color.divide(scene.camera.samples as f64).clamp().to_rgba();
-            });
+            let current = (done as u64 * 100 / pixels_count.max(1) as u64) as u8;
+
+            if current != progress {
+                progress = current;
+                print!("\rProgress: {}%", progress);
+                io::stdout().flush().ok();
+            }
         }
     });
 
     print!("\rProgress 100%\n");
     println!("Rendered in {} seconds", start_time.elapsed().as_secs());
 
-    image
+    // Every camera sample traces one light path, so splats are averaged over all of them
+    let splats = context.splats.into_pixels();
+    let total_samples: u64 = pixels.iter().map(|pixel| pixel.samples as u64).sum();
+    let splat_scale = pixels_count as f64 / total_samples.max(1) as f64;
+
+    let mut colors: Vec<Color> = pixels.iter().zip(splats.iter())
+        .map(|(pixel, splat)| pixel.color.divide(pixel.samples.max(1) as f64).add_color(&splat.multiply(splat_scale)))
+        .collect();
+
+    let mut pass_images: Vec<PassImage> = traced_passes.iter().enumerate().map(|(p, &pass)| PassImage {
+        pass,
+        width: scene.width,
+        height: scene.height,
+        pixels: pixels.iter().map(|pixel| pixel.values[p].clone()).collect(),
+    }).collect();
+
+    if let Some(ref denoiser) = scene.denoiser {
+        let guide = |pass| &pass_images.iter().find(|image| image.pass == pass).unwrap().pixels[..];
+        let guides = denoise::Guides { albedo: guide(Pass::Albedo), normals: guide(Pass::Normal) };
+
+        colors = denoiser.denoise(&colors, &guides, scene.width, scene.height);
+    }
+
+    pass_images.truncate(passes.len());
+
+    let image = ImageBuffer::from_fn(scene.width, scene.height, |x, y| {
+        colors[(y * scene.width + x) as usize].clamp().to_rgba()
+    });
+
+    (image, pass_images)
+}
+
+// Sum of camera samples of the pixel and values of its passes
+#[derive(Clone)]
+struct Pixel {
+    color: Color,
+    samples: u32,
+    values: Vec<Color>,
 }
@@ -1,18 +1,26 @@
 use vector::Vector3;
 use vector::Point;
 use color::Color;
+use ray::Ray;
+use sampler::Sampler;
+use sampling;
+use std::f64::consts::PI;
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub enum Light {
     DirectionalLight(DirectionalLight),
     SphericalLight(SphericalLight)
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct SphericalLight {
     pub position: Point,
     pub intensity: f64,
 
+    // Lights with zero radius are points, bigger ones cast soft shadows and show up in reflections
+    #[serde(default)]
+    pub radius: f64,
+
     #[serde(default="Color::white")]
     pub color: Color,
 
@@ -20,7 +28,7 @@ pub struct SphericalLight {
     pub cast_shadow: bool,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct DirectionalLight {
     pub direction: Vector3,
     pub intensity: f64,
@@ -32,6 +40,17 @@ pub struct DirectionalLight {
     pub cast_shadow: bool,
 }
 
+// Light reaching a point from a direction picked on the light source
+pub struct LightSample {
+    // Normalized direction from the point towards the light
+    pub direction: Vector3,
+    pub distance: f64,
+    pub radiance: Color,
+    // Solid angle density of picked direction. Point and directional lights have single
+    // possible direction, for them density is 1 and radiance is the full light contribution
+    pub pdf: f64,
+}
+
 fn default_cast_shadow() -> bool { true }
 
 impl Light {
@@ -73,4 +92,62 @@ impl Light {
             }
        }
     }
+
+    // Point and directional lights can't be hit by rays, so they can only be sampled directly
+    pub fn is_delta(&self) -> bool {
+        match *self {
+            Light::DirectionalLight(_) => true,
+            Light::SphericalLight(ref s) => s.radius <= 0.0
+       }
+    }
+
+    pub fn sample(&self, point: &Point, sampler: &mut Sampler) -> Option<LightSample> {
+        match *self {
+            Light::SphericalLight(ref s) if s.radius > 0.0 => {
+                let (direction, pdf) = sampling::sample_sphere(&s.position, s.radius, point, sampler)?;
+                let distance = sampling::intersect_sphere(&s.position, s.radius, point, &direction)?;
+
+                Some(LightSample { direction, distance, radiance: s.radiance(), pdf })
+            },
+            _ => Some(LightSample {
+                direction: self.direction_vector(point).normalize().neg(),
+                distance: self.distance(point),
+                radiance: self.color().multiply(self.relative_intensity(point)),
+                pdf: 1.0,
+            })
+        }
+    }
+
+    // Density of sample picking the direction from the point, zero for point and directional lights
+    pub fn pdf(&self, point: &Point) -> f64 {
+        match *self {
+            Light::SphericalLight(ref s) if s.radius > 0.0 => sampling::sphere_pdf(&s.position, s.radius, point),
+            _ => 0.0
+        }
+    }
+
+    // Distance to visible surface of the light, lights without area are never hit
+    pub fn intersect(&self, ray: &Ray) -> Option<f64> {
+        match *self {
+            Light::SphericalLight(ref s) if s.radius > 0.0 => {
+                sampling::intersect_sphere(&s.position, s.radius, &ray.origin, &ray.direction.normalize())
+            },
+            _ => None
+        }
+    }
+
+    // Light emitted by every point of the light surface
+    pub fn radiance(&self) -> Color {
+        match *self {
+            Light::SphericalLight(ref s) if s.radius > 0.0 => s.radiance(),
+            _ => Color::black()
+        }
+    }
+}
+
+impl SphericalLight {
+    // Surface radiance which gives the same irradiance at distance as point light of the same intensity
+    fn radiance(&self) -> Color {
+        self.color.multiply(self.intensity / (4.0 * PI * PI * self.radius * self.radius))
+    }
 }
@@ -0,0 +1,295 @@
+use geometry::Object;
+use integrator::Integrator;
+use light::Light;
+use material::{Material, Coloration};
+use scene::Scene;
+use texture::TextureCache;
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Severity {
+    // scene can't be rendered
+    Error,
+    // scene renders but probably not the way it was intended
+    Warning
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    pub location: String,
+    pub message: String,
+}
+
+impl Issue {
+    fn error(location: &str, message: &str) -> Issue {
+        Issue { severity: Severity::Error, location: location.to_string(), message: message.to_string() }
+    }
+
+    fn warning(location: &str, message: &str) -> Issue {
+        Issue { severity: Severity::Warning, location: location.to_string(), message: message.to_string() }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning"
+        };
+
+        write!(f, "{}: {}: {}", severity, self.location, self.message)
+    }
+}
+
+// Reports all problems of the scene including textures which can't be opened
+pub fn lint(scene: &Scene, search_paths: &[PathBuf]) -> Vec<Issue> {
+    let mut issues = check(scene);
+
+    issues.extend(check_textures(scene, search_paths));
+
+    issues
+}
+
+// Checks scene values without touching the file system
+pub fn check(scene: &Scene) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    check_image(scene, &mut issues);
+
+    for (i, object) in scene.geometry.iter().enumerate() {
+        check_object(scene, &format!("geometry[{}]", i), object, &mut issues);
+    }
+
+    for (i, light) in scene.lights.iter().enumerate() {
+        check_light(scene, &format!("lights[{}]", i), light, &mut issues);
+    }
+
+    issues
+}
+
+pub fn check_textures(scene: &Scene, search_paths: &[PathBuf]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut cache = TextureCache::new(false);
+
+    for (i, object) in scene.geometry.iter().enumerate() {
+        for texture in object.material().textures() {
+            let path = scene.texture_path(&texture.source, search_paths);
+
+            if let Err(e) = cache.load(&path) {
+                let location = format!("geometry[{}].material", i);
+                issues.push(Issue::error(&location, &format!("unable to open texture {}: {}", path.display(), e)));
+            }
+        }
+    }
+
+    issues
+}
+
+fn check_image(scene: &Scene, issues: &mut Vec<Issue>) {
+    let camera = &scene.camera;
+
+    if scene.width == 0 || scene.height == 0 {
+        issues.push(Issue::error("width/height", "image size must be positive"));
+    } else if scene.width <= scene.height {
+        issues.push(Issue::error("width/height", "only landscape images are supported"));
+    }
+
+    if camera.samples == 0 {
+        issues.push(Issue::error("camera.samples", "at least one sample is required"));
+    }
+
+    if let Some(ref adaptive) = camera.adaptive {
+        let max_samples = adaptive.max_samples.unwrap_or(camera.samples);
+
+        if adaptive.threshold <= 0.0 {
+            issues.push(Issue::error("camera.adaptive.threshold", "must be positive"));
+        }
+
+        if max_samples == 0 {
+            issues.push(Issue::error("camera.adaptive.max_samples", "at least one sample is required"));
+        } else if adaptive.min_samples > max_samples {
+            issues.push(Issue::warning("camera.adaptive.min_samples", "is more than max_samples, pixels will stop at max_samples"));
+        }
+
+        if scene.integrator.pixel_grid().is_some() {
+            issues.push(Issue::warning("camera.adaptive", "integrator samples fixed grid, adaptive sampling is ignored"));
+        }
+    }
+
+    if camera.diffuse == 0 {
+        issues.push(Issue::error("camera.diffuse", "at least one ray bounce is required"));
+    }
+
+    if camera.fov <= 0.0 || camera.fov >= 180.0 {
+        issues.push(Issue::error("camera.fov", "must be between 0 and 180 degrees"));
+    }
+
+    if let Integrator::Path(ref path) = scene.integrator {
+        if path.max_depth == 0 {
+            issues.push(Issue::error("integrator.Path.max_depth", "at least one bounce is required"));
+        }
+    }
+
+    // Bidirectional starts light paths only from spherical lights and emissive spheres
+    if let Integrator::Bidirectional(_) = scene.integrator {
+        for (i, light) in scene.lights.iter().enumerate() {
+            if let Light::DirectionalLight(_) = *light {
+                issues.push(Issue::warning(&format!("lights[{}]", i), "directional light can't start light paths, caustics it casts are missing"));
+            }
+        }
+
+        for (i, object) in scene.geometry.iter().enumerate() {
+            if !object.material().emission.is_black() && !matches!(*object, Object::Sphere(_)) {
+                issues.push(Issue::warning(&format!("geometry[{}]", i), "emissive object other than sphere is only found by camera paths hitting it"));
+            }
+        }
+    }
+
+    if let Integrator::Photon(ref photon) = scene.integrator {
+        if photon.max_depth == 0 {
+            issues.push(Issue::error("integrator.Photon.max_depth", "at least one bounce is required"));
+        }
+
+        if photon.photons == 0 {
+            issues.push(Issue::error("integrator.Photon.photons", "at least one photon is required"));
+        }
+
+        if photon.radius <= 0.0 {
+            issues.push(Issue::error("integrator.Photon.radius", "must be positive"));
+        }
+
+        if photon.passes == 0 {
+            issues.push(Issue::error("integrator.Photon.passes", "at least one pass is required"));
+        } else if photon.passes > photon.pass_count(camera) {
+            issues.push(Issue::warning("integrator.Photon.passes", "is more than camera samples, extra passes are skipped"));
+        }
+
+        if photon.alpha <= 0.0 || photon.alpha > 1.0 {
+            issues.push(Issue::error("integrator.Photon.alpha", "must be between 0 and 1"));
+        }
+    }
+
+    if let Integrator::AmbientOcclusion(ref ao) = scene.integrator {
+        if ao.radius <= 0.0 {
+            issues.push(Issue::error("integrator.ao.radius", "must be positive"));
+        }
+
+        if ao.samples == 0 {
+            issues.push(Issue::error("integrator.ao.samples", "at least one sample is required"));
+        }
+    }
+
+    if let Integrator::Whitted(ref whitted) = scene.integrator {
+        if whitted.grid == 0 {
+            issues.push(Issue::error("integrator.Whitted.grid", "at least one sample is required"));
+        }
+
+        if whitted.shininess <= 0.0 {
+            issues.push(Issue::error("integrator.Whitted.shininess", "must be positive"));
+        }
+    }
+
+    if let Some(ref denoiser) = scene.denoiser {
+        if denoiser.color_sigma <= 0.0 || denoiser.normal_sigma <= 0.0 || denoiser.albedo_sigma <= 0.0 {
+            issues.push(Issue::error("denoiser", "sigmas must be positive"));
+        }
+
+        if denoiser.iterations == 0 {
+            issues.push(Issue::warning("denoiser.iterations", "denoiser without iterations does nothing"));
+        } else if denoiser.iterations > denoiser.iteration_count(scene.width, scene.height) {
+            issues.push(Issue::warning("denoiser.iterations", "filter reaches past the image size, extra iterations are skipped"));
+        }
+    }
+
+    if scene.tiles.size == 0 {
+        issues.push(Issue::error("tiles.size", "must be positive"));
+    }
+}
+
+fn check_object(scene: &Scene, location: &str, object: &Object, issues: &mut Vec<Issue>) {
+    match *object {
+        Object::Sphere(ref s) => {
+            let location = format!("{}.Sphere", location);
+
+            if s.radius <= 0.0 {
+                issues.push(Issue::error(&format!("{}.radius", location), "must be positive"));
+            } else if s.center.distance(&scene.camera.position) < s.radius {
+                issues.push(Issue::warning(&location, "camera is inside the sphere"));
+            }
+
+            check_material(&format!("{}.material", location), &s.material, issues);
+        },
+        Object::Plane(ref p) => {
+            let location = format!("{}.Plane", location);
+            let magnitude = p.normal.magnitude();
+
+            if magnitude == 0.0 {
+                issues.push(Issue::error(&format!("{}.normal", location), "must not be zero"));
+            } else if (magnitude - 1.0).abs() > 1e-6 {
+                issues.push(Issue::warning(&format!("{}.normal", location), "is not normalized, shading will be off"));
+            }
+
+            check_material(&format!("{}.material", location), &p.material, issues);
+        }
+    }
+}
+
+fn check_material(location: &str, material: &Material, issues: &mut Vec<Issue>) {
+    let opacity = material.opacity.constant();
+    let reflection = material.reflection.constant();
+
+    // Material::scatter refracts when random number is above opacity,
+    // otherwise reflects when it's below reflection and scatters diffusely otherwise
+    if let (Some(opacity), Some(reflection)) = (opacity, reflection) {
+        if opacity <= 0.0 && reflection > 0.0 {
+            issues.push(Issue::warning(location, "reflection has no effect on fully transparent material"));
+        } else if opacity > 0.0 && reflection >= opacity && !is_default_color(&material.color) {
+            issues.push(Issue::warning(location, "reflection covers whole opacity range, color is never used"));
+        }
+    }
+
+    if let Some(refraction_index) = material.refraction_index.constant() {
+        if refraction_index < 1.0 {
+            issues.push(Issue::warning(&format!("{}.refraction_index", location), "is below 1"));
+        }
+    }
+}
+
+fn check_light(scene: &Scene, location: &str, light: &Light, issues: &mut Vec<Issue>) {
+    match *light {
+        Light::DirectionalLight(ref d) => {
+            if d.direction.magnitude() == 0.0 {
+                issues.push(Issue::error(&format!("{}.DirectionalLight.direction", location), "must not be zero"));
+            }
+        },
+        Light::SphericalLight(ref s) => {
+            if s.radius < 0.0 {
+                issues.push(Issue::error(&format!("{}.SphericalLight.radius", location), "must not be negative"));
+            }
+
+            for (i, object) in scene.geometry.iter().enumerate() {
+                if let Object::Sphere(ref sphere) = *object {
+                    let opaque = sphere.material.opacity.constant().is_some_and(|o| o >= 1.0);
+
+                    if opaque && sphere.center.distance(&s.position) < sphere.radius {
+                        let message = format!("light is inside opaque sphere geometry[{}]", i);
+                        issues.push(Issue::warning(&format!("{}.SphericalLight", location), &message));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn is_default_color(color: &Coloration) -> bool {
+    match *color {
+        Coloration::Color(ref c) => c.r == 1.0 && c.g == 1.0 && c.b == 1.0,
+        _ => false
+    }
+}
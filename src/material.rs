@@ -1,50 +1,104 @@
-use image;
-use image::{DynamicImage, GenericImage};
-use serde::{Deserialize, Deserializer};
 use color::Color;
-use vector::{Vector2, Vector3};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, Visitor, MapAccess};
+use serde::de::value::MapAccessDeserializer;
+use texture::{Texture, TextureCoords};
+use procedural::{Checker, Noise, Turbulence, Marble, Wood};
+use vector::Vector3;
 use std::fmt;
-use std::path::PathBuf;
-use rand::prelude::*;
+use std::f64::consts::PI;
+use sampler::Sampler;
+use sampling;
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Material {
+    // Name of scene material this one was copied from, objects referencing it are saved by name.
+    // Object::material_mut clears it, so edited copies are saved inline
+    #[serde(skip)]
+    pub name: Option<String>,
     #[serde(default="Material::default_color")]
     pub color: Coloration,
     #[serde(default="Material::default_reflection")]
-    pub reflection: f64,
+    pub reflection: Parameter,
     #[serde(default="Material::default_reflection_color")]
     pub reflection_color: Color,
     #[serde(default="Material::default_fizziness")]
-    pub fizziness: f64,
+    pub fizziness: Parameter,
     #[serde(default="Material::default_albedo")]
-    pub albedo: f64,
+    pub albedo: Parameter,
     #[serde(default="Material::default_opacity")]
-    pub opacity: f64,
+    pub opacity: Parameter,
     #[serde(default="Material::default_refraction_index")]
-    pub refraction_index: f64,
+    pub refraction_index: Parameter,
     #[serde(default="Material::default_refraction_color")]
-    pub refraction_color: Color
+    pub refraction_color: Color,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub normal_map: Option<Texture>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub bump_map: Option<Coloration>,
+    #[serde(default="Material::default_bump_strength")]
+    pub bump_strength: f64,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub alpha_mask: Option<Texture>,
+    #[serde(default="Material::default_alpha_cutoff")]
+    pub alpha_cutoff: f64,
+    // Light emitted by the surface, objects with emission act as area lights
+    #[serde(default="Color::black")]
+    pub emission: Color
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub enum Coloration {
     Color(Color),
-    Texture(
-        #[serde(deserialize_with="Coloration::load_texture")]
-        DynamicImage)
+    Texture(Texture),
+    Checker(Checker),
+    Noise(Noise),
+    Turbulence(Turbulence),
+    Marble(Marble),
+    Wood(Wood)
 }
 
+// Scalar material property which is either constant or looked up from texture
+#[derive(Debug, Clone)]
+pub enum Parameter {
+    Constant(f64),
+    Map(ParameterMap)
+}
+
+// Texture luminance is remapped from [0, 1] into [min, max] range
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ParameterMap {
+    pub texture: Coloration,
+    #[serde(default)]
+    pub min: f64,
+    #[serde(default="ParameterMap::default_max")]
+    pub max: f64,
+}
+
+const BUMP_DELTA: f64 = 1e-3;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum RayBehavior {
     Diffuse,
     Reflect,
     Refract
 }
 
+// Direction picked by Material::scatter
+pub struct Scatter {
+    pub direction: Vector3,
+    // Surface color divided by the chance of picking the direction
+    pub attenuation: Color,
+    // Solid angle density of diffuse direction including chance of picking diffuse bounce.
+    // Reflection and refraction are treated as perfectly specular and have zero density
+    pub pdf: f64,
+    pub behavior: RayBehavior,
+}
+
 impl Default for Material {
     fn default() -> Self {
-
         Self {
+            name: None,
             color: Self::default_color(),
             fizziness: Self::default_fizziness(),
             reflection: Self::default_reflection(),
@@ -52,85 +106,231 @@ impl Default for Material {
             albedo: Self::default_albedo(),
             opacity: Self::default_opacity(),
             refraction_index: Self::default_refraction_index(),
-            refraction_color: Self::default_refraction_color()
+            refraction_color: Self::default_refraction_color(),
+            normal_map: None,
+            bump_map: None,
+            bump_strength: Self::default_bump_strength(),
+            alpha_mask: None,
+            alpha_cutoff: Self::default_alpha_cutoff(),
+            emission: Color::black()
         }
     }
 }
 
 impl Material {
     fn default_color() -> Coloration { Coloration::Color(Color::white()) }
-    fn default_fizziness() -> f64 { 0.01 }
-    fn default_reflection() -> f64 { 0.0 }
+    fn default_fizziness() -> Parameter { Parameter::Constant(0.01) }
+    fn default_reflection() -> Parameter { Parameter::Constant(0.0) }
     fn default_reflection_color() -> Color { Color::white() }
-    fn default_albedo() -> f64 { 0.08 }
-    fn default_opacity() -> f64 { 1.0 }
-    fn default_refraction_index() -> f64 { 1.5 }
+    fn default_albedo() -> Parameter { Parameter::Constant(0.08) }
+    fn default_opacity() -> Parameter { Parameter::Constant(1.0) }
+    fn default_refraction_index() -> Parameter { Parameter::Constant(1.5) }
     fn default_refraction_color() -> Color { Color::white() }
+    fn default_bump_strength() -> f64 { 1.0 }
+    fn default_alpha_cutoff() -> f64 { 0.5 }
 
     pub fn uses_texture(&self) -> bool {
         match self.color {
-            Coloration::Color(_) => false,
-            Coloration::Texture(_) => true
+            Coloration::Color(_) => {
+                self.perturbs_normal() || self.alpha_mask.is_some() || self.reflection.is_map() || self.fizziness.is_map() ||
+                    self.albedo.is_map() || self.opacity.is_map() || self.refraction_index.is_map()
+            },
+            _ => true
+        }
+    }
+
+    pub fn perturbs_normal(&self) -> bool {
+        self.normal_map.is_some() || self.bump_map.is_some()
+    }
+
+    pub fn textures(&self) -> Vec<&Texture> {
+        let mut textures = Vec::new();
+
+        textures.extend(self.color.texture());
+        textures.extend(self.normal_map.as_ref());
+        textures.extend(self.bump_map.as_ref().and_then(|b| b.texture()));
+        textures.extend(self.alpha_mask.as_ref());
+
+        let parameters = [&self.reflection, &self.fizziness, &self.albedo, &self.opacity, &self.refraction_index];
+
+        for parameter in parameters {
+            if let Parameter::Map(ref map) = *parameter {
+                textures.extend(map.texture.texture());
+            }
         }
+
+        textures
     }
 
-    pub fn scatter(&self, vec: &Vector3, normal: &Vector3, texture_coords: &Vector2) -> (Vector3, Color) {
-        let mut rng = thread_rng();
-        let rand: f64 = rng.gen();
-        let mut behavior = RayBehavior::Diffuse;
-        let mut output_vec = Vector3::zero();
+    // Image textures used by the material, they are loaded after scene is parsed
+    pub fn textures_mut(&mut self) -> Vec<&mut Texture> {
+        let mut textures = Vec::new();
+
+        textures.extend(self.color.texture_mut());
+        textures.extend(self.normal_map.as_mut());
+        textures.extend(self.bump_map.as_mut().and_then(|b| b.texture_mut()));
+        textures.extend(self.alpha_mask.as_mut());
+
+        let parameters = [
+            &mut self.reflection,
+            &mut self.fizziness,
+            &mut self.albedo,
+            &mut self.opacity,
+            &mut self.refraction_index
+        ];
 
-        if rand > self.opacity {
-            let rand: f64 = rng.gen();
-            let vec_and_behavior = self.try_refraction(rand, vec, normal);
+        for parameter in parameters {
+            if let Parameter::Map(ref mut map) = *parameter {
+                textures.extend(map.texture.texture_mut());
+            }
+        }
+
+        textures
+    }
+
+    pub fn is_cut_out(&self, coords: &TextureCoords) -> bool {
+        match self.alpha_mask {
+            Some(ref mask) => mask.alpha_at(coords) < self.alpha_cutoff,
+            None => false
+        }
+    }
+
+    // Applies normal and bump maps to geometric normal. Tangent and bitangent point in direction
+    // of growing u and v coordinate, so green channel of normal maps points to growing v everywhere
+    pub fn shading_normal(&self, normal: &Vector3, tangent: &Vector3, bitangent: &Vector3, coords: &TextureCoords) -> Vector3 {
+        let tangent = tangent.subtract(&normal.multiply(normal.dot(tangent))).normalize();
+        let frame_bitangent = normal.cross(&tangent);
+        let bitangent = if frame_bitangent.dot(bitangent) < 0.0 { frame_bitangent.neg() } else { frame_bitangent };
+        let mut shading_normal = normal.clone();
+
+        if let Some(ref normal_map) = self.normal_map {
+            let n = normal_map.vector_at(coords);
+
+            shading_normal = tangent.multiply(n.x)
+                .add(&bitangent.multiply(n.y))
+                .add(&normal.multiply(n.z))
+                .normalize();
+        }
+
+        if let Some(ref bump_map) = self.bump_map {
+            let height = bump_map.value_at(coords);
+            let height_u = bump_map.value_at(&coords.shifted(BUMP_DELTA, 0.0, &tangent));
+            let height_v = bump_map.value_at(&coords.shifted(0.0, BUMP_DELTA, &bitangent));
+
+            let gradient = tangent.multiply(height_u - height)
+                .add(&bitangent.multiply(height_v - height))
+                .multiply(self.bump_strength / BUMP_DELTA);
+
+            shading_normal = shading_normal.subtract(&gradient).normalize();
+        }
+
+        shading_normal
+    }
+
+    // Diffuse bounce is cosine weighted, used by physically based integrators
+    pub fn scatter(&self, vec: &Vector3, normal: &Vector3, texture_coords: &TextureCoords, sampler: &mut Sampler) -> Scatter {
+        self.sample_scatter(vec, normal, texture_coords, sampler, false)
+    }
+
+    // Diffuse bounce goes towards random point inside of the unit ball around the tip of the normal,
+    // the original distribution Legacy integrator keeps its look with. Its pdf isn't cosine weighted
+    pub fn scatter_legacy(&self, vec: &Vector3, normal: &Vector3, texture_coords: &TextureCoords, sampler: &mut Sampler) -> Scatter {
+        self.sample_scatter(vec, normal, texture_coords, sampler, true)
+    }
+
+    // Always draws the same five numbers, so dimensions of following bounces don't depend on the branch taken
+    fn sample_scatter(&self, vec: &Vector3, normal: &Vector3, texture_coords: &TextureCoords, sampler: &mut Sampler, in_ball: bool) -> Scatter {
+        let rand = sampler.next_1d();
+        let refraction_rand = sampler.next_1d();
+        let (u, v) = sampler.next_2d();
+        let fuzz_radius = sampler.next_1d().cbrt();
+
+        // Diffuse bounce and fizzy reflection share the random direction
+        let direction = sampling::uniform_sphere(u, v);
+        let fuzz = direction.multiply(fuzz_radius);
+        let behavior;
+        let output_vec;
+        let mut pdf = 0.0;
+
+        if rand > self.opacity.value_at(texture_coords) {
+            let vec_and_behavior = self.try_refraction(refraction_rand, vec, normal, texture_coords, &fuzz);
 
             output_vec = vec_and_behavior.0;
             behavior = vec_and_behavior.1;
-        } else if rand < self.reflection {
-            output_vec = self.reflect(vec, normal);
+        } else if rand < self.reflection.value_at(texture_coords) {
+            output_vec = self.reflect(vec, normal, texture_coords, &fuzz);
             behavior = RayBehavior::Reflect;
         } else {
-            output_vec = normal.add(&Vector3::random_unit());
+            // point on the unit sphere gives cosine weighted direction around the normal
+            output_vec = normal.add(if in_ball { &fuzz } else { &direction });
+            pdf = self.diffuse_pdf(normal, &output_vec.normalize(), texture_coords);
             behavior = RayBehavior::Diffuse;
         }
 
-        (output_vec, self.diffuse_color(behavior, texture_coords))
+        Scatter {
+            direction: output_vec,
+            attenuation: self.diffuse_color(behavior.clone(), texture_coords),
+            pdf,
+            behavior,
+        }
+    }
+
+    // Diffuse part of BRDF, the same for all pairs of directions above the surface
+    pub fn diffuse_brdf(&self, coords: &TextureCoords) -> Color {
+        self.color_at(coords).multiply(self.diffuse_probability(coords) / PI)
+    }
+
+    // Diffuse part of BRDF times cosine between normal and direction towards light
+    pub fn diffuse_brdf_cos(&self, normal: &Vector3, direction: &Vector3, coords: &TextureCoords) -> Color {
+        self.color_at(coords).multiply(self.diffuse_pdf(normal, direction, coords))
     }
 
-    fn schlick(&self, cosine: f64) -> f64 {
-        let r = (1.0 - self.refraction_index) / (1.0 + self.refraction_index);
+    // Density of scatter picking given direction as diffuse bounce
+    pub fn diffuse_pdf(&self, normal: &Vector3, direction: &Vector3, coords: &TextureCoords) -> f64 {
+        self.diffuse_probability(coords) * normal.dot(direction).max(0.0) / PI
+    }
+
+    // Chance of scatter picking diffuse bounce, see branches above
+    pub fn diffuse_probability(&self, coords: &TextureCoords) -> f64 {
+        (self.opacity.value_at(coords) - self.reflection.value_at(coords)).max(0.0)
+    }
+
+    fn schlick(cosine: f64, refraction_index: f64) -> f64 {
+        let r = (1.0 - refraction_index) / (1.0 + refraction_index);
         let r2 = r * r;
 
         r2 + (1.0 - r2) * (1.0 - cosine).powi(5)
     }
 
-    fn try_refraction(&self, rand: f64, vec: &Vector3, normal: &Vector3) -> (Vector3, RayBehavior) {
-        let mut outward_normal;
+    fn try_refraction(&self, rand: f64, vec: &Vector3, normal: &Vector3, coords: &TextureCoords, fuzz: &Vector3) -> (Vector3, RayBehavior) {
+        match self.refraction(vec, normal, coords) {
+            Some((refracted, reflection_prob)) if reflection_prob <= rand => (refracted, RayBehavior::Refract),
+            _ => (self.reflect(vec, normal, coords, fuzz), RayBehavior::Reflect)
+        }
+    }
+
+    // Refracted direction and the chance of the ray being reflected instead, nothing
+    // when the ray is totally reflected
+    pub fn refraction(&self, vec: &Vector3, normal: &Vector3, coords: &TextureCoords) -> Option<(Vector3, f64)> {
+        let outward_normal;
+        let refraction_index = self.refraction_index.value_at(coords);
         let mut cosine = -vec.dot(&normal) / vec.magnitude();
-        let mut ni_over_nt = self.refraction_index;
+        let mut ni_over_nt = refraction_index;
 
         if vec.dot(&normal) > 0.0 {
             outward_normal = normal.neg();
-            cosine = self.refraction_index * cosine;
+            cosine = refraction_index * vec.dot(normal) / vec.magnitude();
         } else {
             outward_normal = normal.clone();
             ni_over_nt = 1.0 / ni_over_nt;
         }
 
-        let reflection_prob = self.schlick(cosine);
-
-        if reflection_prob > rand {
-            return (self.reflect(vec, normal), RayBehavior::Reflect)
-        }
+        let refracted = self.refract(vec, &outward_normal, ni_over_nt)?;
 
-        if let Some(refracted) = self.refract(vec, &outward_normal, ni_over_nt) {
-            (refracted, RayBehavior::Refract)
-        } else {
-            (self.reflect(vec, normal), RayBehavior::Reflect)
-        }
+        Some((refracted, Self::schlick(cosine, refraction_index)))
     }
 
-    pub fn diffuse_color(&self, behavior: RayBehavior, texture_coordinate: &Vector2) -> Color {
+    pub fn diffuse_color(&self, behavior: RayBehavior, texture_coordinate: &TextureCoords) -> Color {
         match behavior {
             RayBehavior::Diffuse => self.color_at(texture_coordinate),
             RayBehavior::Reflect => self.reflection_color.clone(),
@@ -138,7 +338,7 @@ impl Material {
         }
     }
 
-    pub fn color_at(&self, coords: &Vector2) -> Color {
+    pub fn color_at(&self, coords: &TextureCoords) -> Color {
         self.color.color_at(&coords)
     }
 
@@ -154,40 +354,132 @@ impl Material {
         }
     }
 
-    fn reflect(&self, vec: &Vector3, normal: &Vector3) -> Vector3 {
-        vec.reflect(normal).add(&Vector3::random_unit().multiply(self.fizziness))
+    // Fuzz is a random point inside of the unit sphere
+    fn reflect(&self, vec: &Vector3, normal: &Vector3, coords: &TextureCoords, fuzz: &Vector3) -> Vector3 {
+        vec.reflect(normal).add(&fuzz.multiply(self.fizziness.value_at(coords)))
     }
 }
 
-impl Coloration {
-    fn color_at(&self, coords: &Vector2) -> Color {
+impl Parameter {
+    pub fn value_at(&self, coords: &TextureCoords) -> f64 {
         match *self {
-            Coloration::Color(ref color) => { color.clone() }
-            Coloration::Texture(ref texture) => {
-                let x = Coloration::wrap(coords.x, texture.width());
-                let y = Coloration::wrap(coords.y, texture.height());
+            Parameter::Constant(value) => value,
+            Parameter::Map(ref map) => map.value_at(coords)
+        }
+    }
 
-                Color::from_rgba(texture.get_pixel(x, y))
-            }
+    pub fn constant(&self) -> Option<f64> {
+        match *self {
+            Parameter::Constant(value) => Some(value),
+            Parameter::Map(_) => None
         }
     }
 
-    pub fn load_texture<'de, D>(deserializer: D) -> Result<DynamicImage, D::Error>
+    pub fn is_map(&self) -> bool {
+        match *self {
+            Parameter::Constant(_) => false,
+            Parameter::Map(_) => true
+        }
+    }
+}
+
+impl ParameterMap {
+    fn default_max() -> f64 { 1.0 }
+
+    pub fn value_at(&self, coords: &TextureCoords) -> f64 {
+        let t = self.texture.value_at(coords);
+
+        self.min + (self.max - self.min) * t
+    }
+}
+
+// Constants are written as plain numbers so saved scenes look the same as hand written ones
+impl Serialize for Parameter {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        match *self {
+            Parameter::Constant(value) => serializer.serialize_f64(value),
+            Parameter::Map(ref map) => map.serialize(serializer)
+        }
+    }
+}
+
+// Parameter can be given either as a plain number or as a texture map object
+impl<'de> Deserialize<'de> for Parameter {
+    fn deserialize<D>(deserializer: D) -> Result<Parameter, D::Error>
         where D: Deserializer<'de>
     {
-        let path = PathBuf::deserialize(deserializer)?;
-        Ok(image::open(path).expect("Unable to open texture file"))
+        struct ParameterVisitor;
+
+        impl<'de> Visitor<'de> for ParameterVisitor {
+            type Value = Parameter;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("number or texture map")
+            }
+
+            fn visit_f64<E>(self, value: f64) -> Result<Parameter, E>
+                where E: de::Error
+            {
+                Ok(Parameter::Constant(value))
+            }
+
+            fn visit_i64<E>(self, value: i64) -> Result<Parameter, E>
+                where E: de::Error
+            {
+                Ok(Parameter::Constant(value as f64))
+            }
+
+            fn visit_u64<E>(self, value: u64) -> Result<Parameter, E>
+                where E: de::Error
+            {
+                Ok(Parameter::Constant(value as f64))
+            }
+
+            fn visit_map<M>(self, map: M) -> Result<Parameter, M::Error>
+                where M: MapAccess<'de>
+            {
+                ParameterMap::deserialize(MapAccessDeserializer::new(map)).map(Parameter::Map)
+            }
+        }
+
+        deserializer.deserialize_any(ParameterVisitor)
+    }
+}
+
+impl Coloration {
+    pub fn texture(&self) -> Option<&Texture> {
+        match *self {
+            Coloration::Texture(ref texture) => Some(texture),
+            _ => None
+        }
     }
 
-    fn wrap(val: f64, bound: u32) -> u32 {
-        let signed_bound = bound as i32;
-        let float_coord = val * bound as f64;
-        let wrapped_coord = (float_coord as i32) % signed_bound;
+    pub fn texture_mut(&mut self) -> Option<&mut Texture> {
+        match *self {
+            Coloration::Texture(ref mut texture) => Some(texture),
+            _ => None
+        }
+    }
 
-        if wrapped_coord < 0 {
-            (wrapped_coord + signed_bound) as u32
-        } else {
-            wrapped_coord as u32
+    // Number stored in the coloration, images hold plain numbers so they aren't decoded from sRGB
+    fn value_at(&self, coords: &TextureCoords) -> f64 {
+        match *self {
+            Coloration::Texture(ref texture) => texture.raw_at(coords).luminance(),
+            _ => self.color_at(coords).luminance()
+        }
+    }
+
+    fn color_at(&self, coords: &TextureCoords) -> Color {
+        match *self {
+            Coloration::Color(ref color) => { color.clone() }
+            Coloration::Texture(ref texture) => texture.color_at(coords),
+            Coloration::Checker(ref checker) => checker.color_at(coords),
+            Coloration::Noise(ref noise) => noise.color_at(coords),
+            Coloration::Turbulence(ref turbulence) => turbulence.color_at(coords),
+            Coloration::Marble(ref marble) => marble.color_at(coords),
+            Coloration::Wood(ref wood) => wood.color_at(coords)
         }
     }
 }
@@ -196,7 +488,134 @@ impl fmt::Debug for Coloration {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Coloration::Color(ref c) => write!(f, "Color({:?})", c),
-            Coloration::Texture(_) => write!(f, "Texture"),
+            Coloration::Texture(ref t) => write!(f, "{:?}", t),
+            Coloration::Checker(ref c) => write!(f, "{:?}", c),
+            Coloration::Noise(ref n) => write!(f, "{:?}", n),
+            Coloration::Turbulence(ref t) => write!(f, "{:?}", t),
+            Coloration::Marble(ref m) => write!(f, "{:?}", m),
+            Coloration::Wood(ref w) => write!(f, "{:?}", w),
+        }
+    }
+}
+
+// Object material given either inline or as name of material from the scene materials map.
+// Named materials are filled in by Scene::resolve_materials and saved by name again
+pub mod reference {
+    use super::Material;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde::de::{self, Visitor, MapAccess};
+    use serde::de::value::MapAccessDeserializer;
+    use std::fmt;
+
+    pub fn serialize<S>(material: &Material, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        match material.name {
+            Some(ref name) => serializer.serialize_str(name),
+            None => material.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Material, D::Error>
+        where D: Deserializer<'de>
+    {
+        struct ReferenceVisitor;
+
+        impl<'de> Visitor<'de> for ReferenceVisitor {
+            type Value = Material;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("material name or material")
+            }
+
+            fn visit_str<E>(self, name: &str) -> Result<Material, E>
+                where E: de::Error
+            {
+                Ok(Material { name: Some(name.to_string()), ..Material::default() })
+            }
+
+            fn visit_map<M>(self, map: M) -> Result<Material, M::Error>
+                where M: MapAccess<'de>
+            {
+                Material::deserialize(MapAccessDeserializer::new(map))
+            }
+        }
+
+        deserializer.deserialize_any(ReferenceVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geometry::{Intersectable, Object, Plane, Sphere};
+    use image::{Rgba, RgbaImage};
+    use texture::TextureImage;
+    use vector::Vector2;
+    use std::sync::Arc;
+
+    fn coords(u: f64, v: f64) -> TextureCoords {
+        TextureCoords { uv: Vector2 { x: u, y: v }, world: Vector3::zero(), object: Vector3::zero() }
+    }
+
+    // Texture of the image, the first row of pixels is at v = 0
+    fn texture(image: RgbaImage) -> Texture {
+        Texture { image: Some(Arc::new(TextureImage::Encoded(image))), ..Texture::new("test") }
+    }
+
+    #[test]
+    fn parameter_map_reads_plain_numbers() {
+        let map = ParameterMap {
+            texture: Coloration::Texture(texture(RgbaImage::from_pixel(1, 1, Rgba { data: [128, 128, 128, 255] }))),
+            min: 0.0,
+            max: 1.0,
+        };
+        assert!((map.value_at(&coords(0.5, 0.5)) - 128.0 / 255.0).abs() < 1e-9);
+
+        let map = ParameterMap { texture: Coloration::Color(Color { r: 0.25, g: 0.25, b: 0.25 }), min: 1.0, max: 3.0 };
+        assert!((map.value_at(&coords(0.5, 0.5)) - 1.5).abs() < 1e-9);
+    }
+
+    // World direction v texture coordinate grows in at the point, found by central differences
+    fn v_direction(object: &Object, point: &Vector3) -> Vector3 {
+        let axes = [Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0)];
+        let dv = |axis: &Vector3| {
+            let ahead = object.texture_coords(&point.add(&axis.multiply(1e-6))).y;
+            let behind = object.texture_coords(&point.subtract(&axis.multiply(1e-6))).y;
+
+            (ahead - behind) / 2e-6
+        };
+
+        Vector3::new(dv(&axes[0]), dv(&axes[1]), dv(&axes[2])).normalize()
+    }
+
+    #[test]
+    fn bump_map_tilts_normal_away_from_growing_v_on_all_primitives() {
+        // Heights grow with v, every row of the image is one step higher than the previous
+        let mut ramp = RgbaImage::new(1, 256);
+
+        for (_, y, pixel) in ramp.enumerate_pixels_mut() {
+            *pixel = Rgba { data: [y as u8, y as u8, y as u8, 255] };
+        }
+
+        let material = Material { bump_map: Some(Coloration::Texture(texture(ramp))), ..Material::default() };
+        let objects = [
+            (Object::Plane(Plane { origin: Vector3::zero(), normal: Vector3::new(0.0, -1.0, 0.0), material: Material::default() }), Vector3::new(0.3, 0.0, 0.2)),
+            (Object::Sphere(Sphere { center: Vector3::zero(), radius: 1.0, material: Material::default() }), Vector3::new(1.0, 0.3, 0.2).normalize()),
+        ];
+
+        for (object, point) in objects.iter() {
+            let normal = object.surface_normal(point);
+            let mut coords = coords(object.texture_coords(point).x, 0.0);
+            coords.world = point.clone();
+
+            // Just below a row boundary, so the v step of the bump lookup reaches the next row
+            coords.uv.y = 99.9 / 256.0;
+
+            let shading_normal = material.shading_normal(&normal, &object.tangent(point), &object.bitangent(point), &coords);
+            let v = v_direction(object, point);
+
+            assert!(shading_normal.dot(&v) < -0.1, "{:?}: normal {:?} v {:?}", object, shading_normal, v);
         }
     }
 }
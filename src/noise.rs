@@ -0,0 +1,90 @@
+use vector::Point;
+
+// Improved Perlin gradient noise. Lattice gradients are picked by hashing
+// integer coordinates, so no permutation table has to be stored.
+pub fn perlin(point: &Point) -> f64 {
+    let xi = point.x.floor();
+    let yi = point.y.floor();
+    let zi = point.z.floor();
+
+    let x = point.x - xi;
+    let y = point.y - yi;
+    let z = point.z - zi;
+
+    let (xi, yi, zi) = (xi as i64, yi as i64, zi as i64);
+
+    let u = fade(x);
+    let v = fade(y);
+    let w = fade(z);
+
+    let g = |dx: i64, dy: i64, dz: i64| {
+        gradient(hash(xi + dx, yi + dy, zi + dz), x - dx as f64, y - dy as f64, z - dz as f64)
+    };
+
+    lerp(w,
+        lerp(v,
+            lerp(u, g(0, 0, 0), g(1, 0, 0)),
+            lerp(u, g(0, 1, 0), g(1, 1, 0))),
+        lerp(v,
+            lerp(u, g(0, 0, 1), g(1, 0, 1)),
+            lerp(u, g(0, 1, 1), g(1, 1, 1))))
+}
+
+// Fractal Brownian motion: sum of noise octaves with growing frequency and decaying amplitude
+pub fn fbm(point: &Point, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut p = point.clone();
+
+    for _ in 0..octaves {
+        sum += amplitude * perlin(&p);
+        amplitude *= gain;
+        p = p.multiply(lacunarity);
+    }
+
+    sum
+}
+
+// Same as fbm but sums absolute values which gives sharp creases typical for turbulence
+pub fn turbulence(point: &Point, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut p = point.clone();
+
+    for _ in 0..octaves {
+        sum += amplitude * perlin(&p).abs();
+        amplitude *= gain;
+        p = p.multiply(lacunarity);
+    }
+
+    sum
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn hash(x: i64, y: i64, z: i64) -> u64 {
+    let mut h = (x as u64).wrapping_mul(0x8da6_b343)
+        ^ (y as u64).wrapping_mul(0xd816_3841)
+        ^ (z as u64).wrapping_mul(0xcb1a_b31f);
+
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+
+    h
+}
+
+// Picks one of 12 cube edge directions as in Perlin's reference implementation
+fn gradient(hash: u64, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
@@ -0,0 +1,475 @@
+use camera::Camera;
+use color::Color;
+use geometry::Object;
+use integrator;
+use light::Light;
+use material::RayBehavior;
+use ray::Ray;
+use scene::Scene;
+use vector::{Vector3, Point};
+use sampler::{self, Sampler};
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PhotonMapping {
+    // Photons emitted by every light in each pass
+    #[serde(default="PhotonMapping::default_photons")]
+    pub photons: u32,
+    // Radius photons are gathered from when estimating caustics
+    #[serde(default="PhotonMapping::default_radius")]
+    pub radius: f64,
+    // Number of photon maps. Camera samples cycle through them and every next map gathers
+    // from smaller radius, which makes caustics sharper as samples grow (progressive photon mapping)
+    #[serde(default="PhotonMapping::default_passes")]
+    pub passes: u32,
+    // Fraction of photons kept between passes, controls how fast the radius shrinks
+    #[serde(default="PhotonMapping::default_alpha")]
+    pub alpha: f64,
+    // Maximum number of bounces of photons and camera paths
+    #[serde(default="PhotonMapping::default_max_depth")]
+    pub max_depth: u32,
+    // Bounce after which camera paths are randomly terminated by their throughput
+    #[serde(default="PhotonMapping::default_roulette_depth")]
+    pub roulette_depth: u32,
+}
+
+// Light which arrived at diffuse surface after at least one specular bounce
+pub struct Photon {
+    pub position: Point,
+    // Direction the photon was travelling in
+    pub direction: Vector3,
+    pub power: Color,
+}
+
+// Photons stored as balanced kd-tree. Node of every slice is its middle element,
+// its split axis is kept at the same index of `axes`
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<usize>,
+    radius: f64,
+}
+
+// Distance photons start from directional lights, they have no position of their own
+const DIRECTIONAL_DISTANCE: f64 = 1e4;
+
+impl Default for PhotonMapping {
+    fn default() -> PhotonMapping {
+        PhotonMapping {
+            photons: PhotonMapping::default_photons(),
+            radius: PhotonMapping::default_radius(),
+            passes: PhotonMapping::default_passes(),
+            alpha: PhotonMapping::default_alpha(),
+            max_depth: PhotonMapping::default_max_depth(),
+            roulette_depth: PhotonMapping::default_roulette_depth(),
+        }
+    }
+}
+
+impl PhotonMapping {
+    fn default_photons() -> u32 { 100000 }
+    fn default_radius() -> f64 { 0.1 }
+    fn default_passes() -> u32 { 1 }
+    fn default_alpha() -> f64 { 0.7 }
+    fn default_max_depth() -> u32 { 8 }
+    fn default_roulette_depth() -> u32 { 3 }
+
+    // Camera sample n uses map of pass n % passes, so pixels never reach passes past their samples
+    pub fn pass_count(&self, camera: &Camera) -> u32 {
+        let max_samples = camera.adaptive.as_ref().and_then(|adaptive| adaptive.max_samples).unwrap_or(camera.samples);
+
+        self.passes.min(max_samples).max(1)
+    }
+
+    // Builds photon map for every pass
+    pub fn trace_photons(&self, scene: &Scene) -> Vec<PhotonMap> {
+        let mut radius2 = self.radius * self.radius;
+        let mut maps = Vec::new();
+
+        for pass in 0..self.pass_count(&scene.camera) {
+            if pass > 0 {
+                radius2 *= (pass as f64 + self.alpha) / (pass as f64 + 1.0);
+            }
+
+            let mut photons = Vec::new();
+
+            // Photons of every emitter are samples of one sequence, like camera samples of a pixel.
+            // Keys of photon streams count down from the top, so they never match pixel keys
+            let pass_seed = sampler::derive_seed(scene.camera.seed, u64::MAX - pass as u64);
+            let mut emitters = 0;
+            let mut emitter_sampler = || {
+                emitters += 1;
+                Sampler::new(scene.camera.sampler, sampler::derive_seed(pass_seed, emitters), self.photons)
+            };
+
+            for light in scene.lights.iter() {
+                let mut sampler = emitter_sampler();
+
+                for i in 0..self.photons {
+                    sampler.start_sample(i);
+
+                    if let Some((ray, power)) = emit_from_light(scene, light, self.photons, &mut sampler) {
+                        self.trace_photon(scene, ray, power, &mut photons, &mut sampler);
+                    }
+                }
+            }
+
+            for object in scene.geometry.iter() {
+                if let Object::Sphere(ref s) = *object {
+                    if s.material.emission.is_black() {
+                        continue;
+                    }
+
+                    let mut sampler = emitter_sampler();
+
+                    for i in 0..self.photons {
+                        sampler.start_sample(i);
+
+                        let (ray, power) = emit_from_sphere(&s.center, s.radius, &s.material.emission, self.photons, &mut sampler);
+                        self.trace_photon(scene, ray, power, &mut photons, &mut sampler);
+                    }
+                }
+            }
+
+            maps.push(PhotonMap::new(photons, radius2.sqrt()));
+        }
+
+        maps
+    }
+
+    // Follows the photon through specular bounces and stores it at every surface with diffuse part.
+    // Photons stop at the first diffuse bounce, so the map holds caustics only
+    fn trace_photon(&self, scene: &Scene, mut ray: Ray, mut power: Color, photons: &mut Vec<Photon>, sampler: &mut Sampler) {
+        let mut specular = false;
+
+        for _ in 0..self.max_depth {
+            let intersection = match scene.trace(&ray) {
+                Some(intersection) => intersection,
+                None => break
+            };
+
+            let material = intersection.object.material();
+            let surface = scene.surface(&ray, &intersection);
+
+            if specular && material.diffuse_probability(&surface.coords) > 0.0 {
+                photons.push(Photon { position: surface.point.clone(), direction: ray.direction.clone(), power: power.clone() });
+            }
+
+            let scatter = material.scatter(&ray.direction, &surface.normal, &surface.coords, sampler);
+
+            if scatter.behavior == RayBehavior::Diffuse {
+                break;
+            }
+
+            power = power.multiply_color(&scatter.attenuation);
+            specular = true;
+            ray = Ray { origin: surface.point, direction: scatter.direction.normalize() };
+        }
+    }
+
+    // Path tracer with next event estimation where light focused by specular surfaces onto diffuse
+    // ones comes from the photon map. Bounced rays reaching light through specular surfaces
+    // after a diffuse bounce carry the same light, so it's skipped
+    pub fn radiance(&self, scene: &Scene, ray: &Ray, map: &PhotonMap, sampler: &mut Sampler) -> Color {
+        let mut radiance = Color::black();
+        let mut throughput = Color::white();
+        let mut ray = Ray { origin: ray.origin.clone(), direction: ray.direction.normalize() };
+
+        let mut bounce_pdf = 0.0;
+        let mut diffuse = false;
+        let mut caustic = false;
+
+        for depth in 0..self.max_depth {
+            let intersection = scene.trace(&ray);
+            let max_distance = intersection.as_ref().map_or(f64::INFINITY, |i| i.distance);
+
+            if let Some((light_distance, light)) = integrator::nearest_light(scene, &ray) {
+                if light_distance < max_distance {
+                    if !caustic {
+                        let weight = integrator::mis_weight(bounce_pdf, light.pdf(&ray.origin));
+                        radiance = radiance.add_color(&throughput.multiply_color(&light.radiance()).multiply(weight));
+                    }
+
+                    break;
+                }
+            }
+
+            let intersection = match intersection {
+                Some(intersection) => intersection,
+                None => {
+                    radiance = radiance.add_color(&throughput.multiply_color(&scene.background(&ray.direction)));
+                    break;
+                }
+            };
+
+            let object = intersection.object;
+            let material = object.material();
+            let surface = scene.surface(&ray, &intersection);
+
+            if !caustic && !material.emission.is_black() {
+                let weight = integrator::mis_weight(bounce_pdf, integrator::emitter_pdf(object, &ray.origin));
+                radiance = radiance.add_color(&throughput.multiply_color(&material.emission).multiply(weight));
+            }
+
+            if material.diffuse_probability(&surface.coords) > 0.0 {
+                let direct = integrator::sample_lights(scene, object, material, &surface, sampler);
+                let caustics = map.estimate(&surface.point, &surface.normal).multiply_color(&material.diffuse_brdf(&surface.coords));
+
+                radiance = radiance.add_color(&throughput.multiply_color(&direct.add_color(&caustics)));
+            }
+
+            let scatter = material.scatter(&ray.direction, &surface.normal, &surface.coords, sampler);
+            throughput = throughput.multiply_color(&scatter.attenuation);
+
+            if scatter.behavior == RayBehavior::Diffuse {
+                bounce_pdf = scatter.pdf;
+                diffuse = true;
+                caustic = false;
+            } else {
+                bounce_pdf = 0.0;
+                caustic = diffuse;
+            }
+
+            if depth >= self.roulette_depth {
+                let survival = throughput.r.max(throughput.g).max(throughput.b).min(0.95);
+                let rand = sampler.next_1d();
+
+                if survival <= 0.0 || rand > survival {
+                    break;
+                }
+
+                throughput = throughput.divide(survival);
+            }
+
+            ray = Ray { origin: surface.point, direction: scatter.direction.normalize() };
+        }
+
+        radiance
+    }
+}
+
+// Photon leaving the light and its share of light power
+fn emit_from_light(scene: &Scene, light: &Light, count: u32, sampler: &mut Sampler) -> Option<(Ray, Color)> {
+    match *light {
+        Light::SphericalLight(ref s) if s.radius > 0.0 => {
+            Some(emit_from_sphere(&s.position, s.radius, &light.radiance(), count, sampler))
+        },
+        Light::SphericalLight(ref s) => {
+            let ray = Ray { origin: s.position.clone(), direction: sampler.direction() };
+
+            Some((ray, s.color.multiply(s.intensity / count as f64)))
+        },
+        Light::DirectionalLight(ref d) => {
+            // Photons are shot through a disk covering all spheres, planes are infinite and
+            // can't focus light anyway
+            let (center, radius) = spheres_bounds(scene)?;
+            let direction = d.direction.normalize();
+            let (u, v) = disk_axes(&direction);
+
+            let (r1, r2) = sampler.next_2d();
+            let r = radius * r1.sqrt();
+            let phi = 2.0 * PI * r2;
+
+            let origin = center
+                .add(&u.multiply(r * phi.cos()))
+                .add(&v.multiply(r * phi.sin()))
+                .subtract(&direction.multiply(DIRECTIONAL_DISTANCE));
+
+            let area = PI * radius * radius;
+
+            Some((Ray { origin, direction }, d.color.multiply(d.intensity * area / count as f64)))
+        }
+    }
+}
+
+// Uniform point on the sphere and cosine weighted direction around its normal. Cosine and both
+// densities cancel out to the same power for every photon
+fn emit_from_sphere(center: &Point, radius: f64, radiance: &Color, count: u32, sampler: &mut Sampler) -> (Ray, Color) {
+    let normal = sampler.direction();
+    let direction = normal.add(&sampler.direction()).normalize();
+    let area = 4.0 * PI * radius * radius;
+
+    let ray = Ray { origin: center.add(&normal.multiply(radius)), direction };
+
+    (ray, radiance.multiply(PI * area / count as f64))
+}
+
+// Bounding sphere of all spheres in the scene
+fn spheres_bounds(scene: &Scene) -> Option<(Point, f64)> {
+    let spheres: Vec<_> = scene.geometry.iter()
+        .filter_map(|o| match *o { Object::Sphere(ref s) => Some(s), _ => None })
+        .collect();
+
+    if spheres.is_empty() {
+        return None;
+    }
+
+    let center = spheres.iter()
+        .fold(Vector3::zero(), |sum, s| sum.add(&s.center))
+        .multiply(1.0 / spheres.len() as f64);
+
+    let radius = spheres.iter()
+        .map(|s| s.center.distance(&center) + s.radius)
+        .fold(0.0, f64::max);
+
+    Some((center, radius))
+}
+
+fn disk_axes(normal: &Vector3) -> (Vector3, Vector3) {
+    let helper = if normal.x.abs() > 0.9 { Vector3::new(0.0, 1.0, 0.0) } else { Vector3::new(1.0, 0.0, 0.0) };
+    let u = helper.cross(normal).normalize();
+    let v = normal.cross(&u);
+
+    (u, v)
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>, radius: f64) -> PhotonMap {
+        let mut axes = vec![0; photons.len()];
+
+        build(&mut photons, &mut axes);
+
+        PhotonMap { photons, axes, radius }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    // Irradiance at the point estimated from density of photons arriving at the front side of the surface
+    pub fn estimate(&self, point: &Point, normal: &Vector3) -> Color {
+        let mut power = Color::black();
+
+        gather(&self.photons, &self.axes, point, self.radius * self.radius, &mut |photon| {
+            if photon.direction.dot(normal) < 0.0 {
+                power = power.add_color(&photon.power);
+            }
+        });
+
+        power.divide(PI * self.radius * self.radius)
+    }
+}
+
+fn coordinate(point: &Point, axis: usize) -> f64 {
+    match axis {
+        0 => point.x,
+        1 => point.y,
+        _ => point.z
+    }
+}
+
+// Splits the slice at median along its longest side and does the same for both halves
+fn build(photons: &mut [Photon], axes: &mut [usize]) {
+    if photons.is_empty() {
+        return;
+    }
+
+    let extent = |axis| {
+        photons.iter()
+            .map(|p| coordinate(&p.position, axis))
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), c| (min.min(c), max.max(c)))
+    };
+
+    let axis = (0..3)
+        .map(|axis| { let (min, max) = extent(axis); (axis, max - min) })
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+        .map_or(0, |(axis, _)| axis);
+
+    let middle = photons.len() / 2;
+
+    photons.select_nth_unstable_by(middle, |a, b| {
+        coordinate(&a.position, axis).partial_cmp(&coordinate(&b.position, axis)).unwrap_or(Ordering::Equal)
+    });
+
+    axes[middle] = axis;
+
+    let (left, right) = photons.split_at_mut(middle);
+    let (left_axes, right_axes) = axes.split_at_mut(middle);
+
+    build(left, left_axes);
+    build(&mut right[1..], &mut right_axes[1..]);
+}
+
+// Calls visit for every photon closer to the point than square root of radius2
+fn gather<F: FnMut(&Photon)>(photons: &[Photon], axes: &[usize], point: &Point, radius2: f64, visit: &mut F) {
+    if photons.is_empty() {
+        return;
+    }
+
+    let middle = photons.len() / 2;
+    let photon = &photons[middle];
+    let axis = axes[middle];
+
+    if photon.position.subtract(point).norm() <= radius2 {
+        visit(photon);
+    }
+
+    let delta = coordinate(point, axis) - coordinate(&photon.position, axis);
+    let (near, far) = if delta < 0.0 { (0..middle, middle + 1..photons.len()) } else { (middle + 1..photons.len(), 0..middle) };
+
+    gather(&photons[near.clone()], &axes[near], point, radius2, visit);
+
+    if delta * delta <= radius2 {
+        gather(&photons[far.clone()], &axes[far], point, radius2, visit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Photons scattered in a unit cube by a fixed linear congruential generator
+    fn photons(count: usize) -> Vec<Photon> {
+        let mut state = 12345u64;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+
+        (0..count).map(|i| Photon {
+            position: Vector3::new(next(), next(), next()),
+            direction: Vector3::new(0.0, if i % 2 == 0 { -1.0 } else { 1.0 }, 0.0),
+            power: Color { r: i as f64, g: 1.0, b: 0.0 },
+        }).collect()
+    }
+
+
+    #[test]
+    fn gather_matches_brute_force() {
+        let all = photons(500);
+        let map = PhotonMap::new(photons(500), 0.15);
+        let queries = [Vector3::new(0.5, 0.5, 0.5), Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.9, 0.1, 0.3),
+                       Vector3::new(1.2, 0.5, 0.5), Vector3::new(0.25, 0.75, 0.6)];
+
+        for point in queries.iter() {
+            let radius2 = map.radius * map.radius;
+            let expected: Vec<&Photon> = all.iter().filter(|p| p.position.subtract(point).norm() <= radius2).collect();
+
+            // Photons are identified by their red power, which is their index
+            let mut gathered = Vec::new();
+            gather(&map.photons, &map.axes, point, radius2, &mut |photon| gathered.push(photon.power.r as u64));
+            gathered.sort();
+
+            assert_eq!(gathered, expected.iter().map(|p| p.power.r as u64).collect::<Vec<_>>());
+
+            // Only photons arriving at the front side of the surface count
+            let normal = Vector3::new(0.0, 1.0, 0.0);
+            let power: f64 = expected.iter().filter(|p| p.direction.dot(&normal) < 0.0).map(|p| p.power.g).sum();
+            let estimate = map.estimate(point, &normal);
+
+            assert!((estimate.g - power / (PI * radius2)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn empty_map() {
+        let map = PhotonMap::new(Vec::new(), 0.1);
+
+        assert!(map.is_empty());
+        assert_eq!(map.estimate(&Vector3::zero(), &Vector3::new(0.0, 1.0, 0.0)).g, 0.0);
+    }
+}
@@ -0,0 +1,256 @@
+use error::SceneError;
+use format::SceneFormat;
+use geometry::Object;
+use light::Light;
+use material::Material;
+use scene::Scene;
+use serde_json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Value of scene variable. Variables are referenced as "${name}" anywhere in the scene file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Variable {
+    Number(f64),
+    Bool(bool),
+    Text(String)
+}
+
+// Scene file fields which have to be known before the rest of the file can be parsed
+#[derive(Deserialize, Default)]
+struct Preamble {
+    #[serde(default)]
+    include: Vec<PathBuf>,
+    #[serde(default)]
+    variables: HashMap<String, Variable>,
+}
+
+// Included file, e.g. material library or light rig. All parts are optional
+#[derive(Deserialize, Default)]
+struct Fragment {
+    #[serde(default)]
+    materials: HashMap<String, Material>,
+    #[serde(default)]
+    textures: HashMap<String, PathBuf>,
+    #[serde(default)]
+    geometry: Vec<Object>,
+    #[serde(default)]
+    lights: Vec<Light>,
+}
+
+// Scene file contents with variables substituted
+pub struct Source {
+    pub format: SceneFormat,
+    pub contents: String,
+    // Included files, relative paths are resolved against folder of the including file
+    pub include: Vec<PathBuf>,
+    // Variables visible in the file and files it includes
+    pub variables: HashMap<String, Variable>,
+}
+
+impl Variable {
+    // Replacement for placeholder which is the whole string value
+    fn literal(&self) -> String {
+        match *self {
+            Variable::Number(n) => n.to_string(),
+            Variable::Bool(b) => b.to_string(),
+            Variable::Text(ref s) => serde_json::to_string(s).unwrap_or_default()
+        }
+    }
+
+    // Replacement for placeholder inside of a longer string
+    fn text(&self) -> String {
+        match *self {
+            Variable::Number(n) => n.to_string(),
+            Variable::Bool(b) => b.to_string(),
+            Variable::Text(ref s) => s.clone()
+        }
+    }
+}
+
+// Reads scene file and substitutes its variables. Inherited variables come from including files
+// and take precedence, so included files can declare defaults which the scene overrides
+pub fn read(path: &Path, inherited: &HashMap<String, Variable>) -> Result<Source, SceneError> {
+    let format = SceneFormat::from_path(path)?;
+    let contents = fs::read_to_string(path)?;
+    let preamble: Preamble = format.parse(&contents)?;
+
+    let mut variables = preamble.variables;
+    variables.extend(inherited.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+    let folder = path.parent().unwrap_or(Path::new(""));
+
+    Ok(Source {
+        contents: substitute(&contents, &variables)?,
+        include: preamble.include.iter().map(|p| folder.join(p)).collect(),
+        format,
+        variables,
+    })
+}
+
+// Merges included file into the scene. Materials and named textures defined by the scene win over
+// included ones, objects and lights are appended. Nested includes are merged first
+pub fn include(scene: &mut Scene, path: &Path, variables: &HashMap<String, Variable>,
+               stack: &mut Vec<PathBuf>) -> Result<(), SceneError> {
+    let wrap = |error| SceneError::Include { path: path.to_path_buf(), error: Box::new(error) };
+
+    let canonical = fs::canonicalize(path).map_err(|e| wrap(e.into()))?;
+
+    if stack.contains(&canonical) {
+        return Err(wrap(SceneError::invalid_value("include", "scene includes itself")));
+    }
+
+    stack.push(canonical.clone());
+
+    let source = read(&canonical, variables).map_err(wrap)?;
+    let mut fragment: Fragment = source.format.parse(&source.contents).map_err(wrap)?;
+
+    for nested in &source.include {
+        include(scene, nested, &source.variables, stack).map_err(wrap)?;
+    }
+
+    stack.pop();
+
+    // Paths in included file are relative to its own folder, not to the scene one
+    let folder = canonical.parent().unwrap_or(Path::new("")).to_path_buf();
+
+    for path in fragment.textures.values_mut() {
+        *path = folder.join(&*path);
+    }
+
+    {
+        let textures = &fragment.textures;
+        let names = |source: &str| scene.textures.contains_key(source) || textures.contains_key(source);
+        let materials = fragment.materials.values_mut()
+            .chain(fragment.geometry.iter_mut().map(|o| o.material_mut()));
+
+        for material in materials {
+            for texture in material.textures_mut() {
+                if !names(&texture.source) {
+                    texture.source = folder.join(&texture.source).to_string_lossy().into_owned();
+                }
+            }
+        }
+    }
+
+    for (name, material) in fragment.materials {
+        scene.materials.entry(name).or_insert(material);
+    }
+
+    for (name, path) in fragment.textures {
+        scene.textures.entry(name).or_insert(path);
+    }
+
+    scene.geometry.extend(fragment.geometry);
+    scene.lights.extend(fragment.lights);
+
+    Ok(())
+}
+
+// Replaces "${name}" placeholders. Placeholder quoted as a whole value is replaced together
+// with the quotes so numbers and booleans keep their type
+fn substitute(contents: &str, variables: &HashMap<String, Variable>) -> Result<String, SceneError> {
+    let mut result = String::with_capacity(contents.len());
+    let mut rest = contents;
+
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break
+        };
+
+        let name = &rest[start + 2..end];
+        let variable = variables.get(name)
+            .ok_or_else(|| SceneError::invalid_value("variables", &format!("undefined variable '{}'", name)))?;
+
+        let quote = rest[..start].chars().last().filter(|&c| c == '"' || c == '\'');
+
+        if quote.is_some_and(|q| rest[end + 1..].starts_with(q)) {
+            result.push_str(&rest[..start - 1]);
+            result.push_str(&variable.literal());
+            rest = &rest[end + 2..];
+        } else {
+            result.push_str(&rest[..start]);
+            result.push_str(&variable.text());
+            rest = &rest[end + 1..];
+        }
+    }
+
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn variables(list: &[(&str, Variable)]) -> HashMap<String, Variable> {
+        list.iter().map(|(name, value)| (name.to_string(), value.clone())).collect()
+    }
+
+    // Fresh folder for scene files of one test
+    fn folder(name: &str) -> PathBuf {
+        let folder = env::temp_dir().join(format!("raytracer-preprocess-{}-{}", name, process::id()));
+
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    const SCENE: &str = r#""width": 4, "height": 4, "geometry": [],
+        "camera": { "fov": 60.0, "samples": 1, "diffuse": 1, "position": { "x": 0.0, "y": 0.0, "z": 0.0 } }"#;
+
+    #[test]
+    fn quoted_placeholder_keeps_type() {
+        let variables = variables(&[("size", Variable::Number(2.5)), ("flag", Variable::Bool(true)), ("name", Variable::Text("wood".to_string()))]);
+        let result = substitute(r#"{ "a": "${size}", "b": '${flag}', "c": "${name}" }"#, &variables).unwrap();
+
+        assert_eq!(result, r#"{ "a": 2.5, "b": true, "c": "wood" }"#);
+    }
+
+    #[test]
+    fn placeholder_inside_of_string() {
+        let variables = variables(&[("name", Variable::Text("wood".to_string())), ("size", Variable::Number(2.0))]);
+        let result = substitute(r#"{ "path": "textures/${name}_${size}.png" }"#, &variables).unwrap();
+
+        assert_eq!(result, r#"{ "path": "textures/wood_2.png" }"#);
+    }
+
+    #[test]
+    fn undefined_variable() {
+        assert!(substitute(r#"{ "a": "${missing}" }"#, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn scene_overrides_included_variables() {
+        let folder = folder("override");
+
+        fs::write(folder.join("scene.json"), format!(r#"{{ {}, "include": ["lights.json"], "variables": {{ "power": 50.0 }}, "lights": [] }}"#, SCENE)).unwrap();
+        fs::write(folder.join("lights.json"), r#"{ "variables": { "power": 10.0 },
+            "lights": [{ "SphericalLight": { "position": { "x": 0.0, "y": 1.0, "z": 0.0 }, "color": { "r": 1.0, "g": 1.0, "b": 1.0 }, "intensity": "${power}" } }] }"#).unwrap();
+
+        let scene = Scene::parse(&folder.join("scene.json")).unwrap();
+
+        match scene.lights[..] {
+            [Light::SphericalLight(ref light)] => assert_eq!(light.intensity, 50.0),
+            _ => panic!("included light is missing")
+        }
+    }
+
+    #[test]
+    fn include_cycle() {
+        let folder = folder("cycle");
+
+        fs::write(folder.join("scene.json"), format!(r#"{{ {}, "include": ["lights.json"], "lights": [] }}"#, SCENE)).unwrap();
+        fs::write(folder.join("lights.json"), r#"{ "include": ["scene.json"] }"#).unwrap();
+
+        match Scene::parse(&folder.join("scene.json")) {
+            Err(error @ SceneError::Include { .. }) => assert!(error.to_string().contains("scene includes itself")),
+            _ => panic!("include cycle isn't reported")
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use color::Color;
+use noise;
+use texture::TextureCoords;
+use vector::Point;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TextureSpace {
+    Uv,
+    Object,
+    World
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Checker {
+    #[serde(default="Color::white")]
+    pub even: Color,
+    #[serde(default="Color::black")]
+    pub odd: Color,
+    #[serde(default="default_scale")]
+    pub scale: f64,
+    #[serde(default="default_uv_space")]
+    pub space: TextureSpace,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Noise {
+    #[serde(default="Color::black")]
+    pub low: Color,
+    #[serde(default="Color::white")]
+    pub high: Color,
+    #[serde(default="default_scale")]
+    pub scale: f64,
+    #[serde(default="default_object_space")]
+    pub space: TextureSpace,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Turbulence {
+    #[serde(default="Color::black")]
+    pub low: Color,
+    #[serde(default="Color::white")]
+    pub high: Color,
+    #[serde(default="default_scale")]
+    pub scale: f64,
+    #[serde(default="default_octaves")]
+    pub octaves: u32,
+    #[serde(default="default_lacunarity")]
+    pub lacunarity: f64,
+    #[serde(default="default_gain")]
+    pub gain: f64,
+    #[serde(default="default_object_space")]
+    pub space: TextureSpace,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Marble {
+    #[serde(default="Color::white")]
+    pub base: Color,
+    #[serde(default="Color::black")]
+    pub vein: Color,
+    #[serde(default="default_scale")]
+    pub scale: f64,
+    #[serde(default="default_octaves")]
+    pub octaves: u32,
+    #[serde(default="default_distortion")]
+    pub distortion: f64,
+    #[serde(default="default_object_space")]
+    pub space: TextureSpace,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Wood {
+    #[serde(default="default_wood_light")]
+    pub light: Color,
+    #[serde(default="default_wood_dark")]
+    pub dark: Color,
+    #[serde(default="default_scale")]
+    pub scale: f64,
+    #[serde(default="default_rings")]
+    pub rings: f64,
+    #[serde(default="default_octaves")]
+    pub octaves: u32,
+    #[serde(default="default_distortion")]
+    pub distortion: f64,
+    #[serde(default="default_object_space")]
+    pub space: TextureSpace,
+}
+
+fn default_scale() -> f64 { 1.0 }
+fn default_octaves() -> u32 { 6 }
+fn default_lacunarity() -> f64 { 2.0 }
+fn default_gain() -> f64 { 0.5 }
+fn default_distortion() -> f64 { 5.0 }
+fn default_rings() -> f64 { 8.0 }
+fn default_uv_space() -> TextureSpace { TextureSpace::Uv }
+fn default_object_space() -> TextureSpace { TextureSpace::Object }
+fn default_wood_light() -> Color { Color { r: 0.79, g: 0.6, b: 0.37 } }
+fn default_wood_dark() -> Color { Color { r: 0.45, g: 0.28, b: 0.14 } }
+
+impl TextureSpace {
+    // UV coordinates are treated as a point on z = 0 plane so every pattern can be evaluated in 3D
+    pub fn point(&self, coords: &TextureCoords) -> Point {
+        match *self {
+            TextureSpace::Uv => Point::new(coords.uv.x, coords.uv.y, 0.0),
+            TextureSpace::Object => coords.object.clone(),
+            TextureSpace::World => coords.world.clone(),
+        }
+    }
+}
+
+impl Checker {
+    pub fn color_at(&self, coords: &TextureCoords) -> Color {
+        let p = self.space.point(coords).multiply(self.scale);
+        let sum = match self.space {
+            TextureSpace::Uv => p.x.floor() + p.y.floor(),
+            _ => p.x.floor() + p.y.floor() + p.z.floor()
+        };
+
+        if sum as i64 % 2 == 0 { self.even.clone() } else { self.odd.clone() }
+    }
+}
+
+impl Noise {
+    pub fn color_at(&self, coords: &TextureCoords) -> Color {
+        let p = self.space.point(coords).multiply(self.scale);
+        let t = 0.5 * (noise::perlin(&p) + 1.0);
+
+        Color::lerp(&self.low, &self.high, t.clamp(0.0, 1.0))
+    }
+}
+
+impl Turbulence {
+    pub fn color_at(&self, coords: &TextureCoords) -> Color {
+        let p = self.space.point(coords).multiply(self.scale);
+        let t = noise::turbulence(&p, self.octaves, self.lacunarity, self.gain);
+
+        Color::lerp(&self.low, &self.high, t.clamp(0.0, 1.0))
+    }
+}
+
+impl Marble {
+    pub fn color_at(&self, coords: &TextureCoords) -> Color {
+        let p = self.space.point(coords).multiply(self.scale);
+        let turbulence = noise::turbulence(&p, self.octaves, 2.0, 0.5);
+        let t = 0.5 * (1.0 + (p.x + self.distortion * turbulence).sin());
+
+        Color::lerp(&self.vein, &self.base, t)
+    }
+}
+
+impl Wood {
+    pub fn color_at(&self, coords: &TextureCoords) -> Color {
+        let p = self.space.point(coords).multiply(self.scale);
+        let distortion = self.distortion * 0.1 * noise::fbm(&p, self.octaves, 2.0, 0.5);
+
+        // rings grow around local y axis
+        let rings = ((p.x * p.x + p.z * p.z).sqrt() + distortion) * self.rings;
+        let t = rings - rings.floor();
+
+        Color::lerp(&self.light, &self.dark, t)
+    }
+}
@@ -0,0 +1,397 @@
+use sampling;
+use vector::Vector3;
+
+// Sequence random numbers of every camera sample are drawn from. Low discrepancy sequences
+// spread samples of a pixel more evenly than independent random numbers, so images converge faster
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum SamplerKind {
+    // Independent random numbers
+    #[default]
+    Independent,
+    // Every dimension is split into camera.samples strata, each sample gets a random point
+    // in its own stratum. Strata are shuffled differently for every dimension
+    Stratified,
+    // Halton sequence with Owen scrambled digits, scrambling differs for every pixel and dimension
+    Halton,
+    // Sobol sequence with Owen scrambling. Dimensions past the fourth reuse the first four
+    // with different scrambling
+    Sobol,
+}
+
+// Random numbers for one pixel. Numbers are drawn in the same order for every sample, so n-th
+// number of all samples belongs to the same dimension of the sequence
+pub struct Sampler {
+    kind: SamplerKind,
+    seed: u64,
+    // Samples the pixel is expected to get, used as number of strata
+    samples: u32,
+    index: u32,
+    dimension: u32,
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131
+];
+
+const SOBOL_DIRECTIONS: [[u32; 32]; 4] = sobol_directions();
+
+impl Sampler {
+    pub fn new(kind: SamplerKind, seed: u64, samples: u32) -> Sampler {
+        Sampler { kind, seed, samples: samples.max(1), index: 0, dimension: 0 }
+    }
+
+    // Starts sample with given index, numbers are drawn from the first dimension again
+    pub fn start_sample(&mut self, index: u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    // Number in [0, 1) from the next dimension
+    pub fn next_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        match self.kind {
+            SamplerKind::Independent => self.independent(dimension),
+            SamplerKind::Stratified => self.stratified(dimension),
+            SamplerKind::Halton => self.halton(dimension),
+            SamplerKind::Sobol => self.sobol(dimension),
+        }
+    }
+
+    pub fn next_2d(&mut self) -> (f64, f64) {
+        let u = self.next_1d();
+
+        (u, self.next_1d())
+    }
+
+    // Integer in [0, count)
+    pub fn below(&mut self, count: usize) -> usize {
+        ((self.next_1d() * count as f64) as usize).min(count.saturating_sub(1))
+    }
+
+    // Uniformly distributed direction on the unit sphere
+    pub fn direction(&mut self) -> Vector3 {
+        let (u, v) = self.next_2d();
+
+        sampling::uniform_sphere(u, v)
+    }
+
+    fn independent(&self, dimension: u32) -> f64 {
+        to_unit(hash(hash(self.seed ^ self.index as u64) ^ dimension as u64))
+    }
+
+    fn stratified(&self, dimension: u32) -> f64 {
+        // Samples past the expected count don't have strata left
+        if self.index >= self.samples {
+            return self.independent(dimension);
+        }
+
+        let key = hash(self.seed ^ ((dimension as u64) << 32));
+        let stratum = permute(self.index, self.samples, key as u32);
+
+        (stratum as f64 + self.independent(dimension)) / self.samples as f64
+    }
+
+    fn halton(&self, dimension: u32) -> f64 {
+        let base = match PRIMES.get(dimension as usize) {
+            Some(&base) => base,
+            None => return self.independent(dimension)
+        };
+
+        scrambled_radical_inverse(base, self.index, hash(self.seed ^ ((dimension as u64) << 32)))
+    }
+
+    fn sobol(&self, dimension: u32) -> f64 {
+        let group_seed = hash(self.seed ^ ((dimension as u64 / 4) << 32)) as u32;
+        let index = nested_uniform_scramble(self.index, group_seed);
+        let value = sobol(index, dimension as usize % 4);
+
+        nested_uniform_scramble(value, hash(group_seed as u64 ^ dimension as u64) as u32) as f64 / 4294967296.0
+    }
+}
+
+// Seed of independent stream of numbers, e.g. of one pixel
+pub fn derive_seed(seed: u64, key: u64) -> u64 {
+    hash(hash(seed) ^ key)
+}
+
+fn to_unit(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// SplitMix64 finalizer, turns any key into well distributed bits
+fn hash(key: u64) -> u64 {
+    let mut x = key.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+    x ^ (x >> 31)
+}
+
+// Every digit is permuted depending on the digits before it
+fn scrambled_radical_inverse(base: u32, index: u32, seed: u64) -> f64 {
+    let mut key = seed;
+
+    radical_inverse(base, index, |digit| {
+        let permuted = permute(digit, base, key as u32);
+        key = hash(key ^ digit as u64);
+
+        permuted
+    })
+}
+
+// Digits of the index mapped by the function and mirrored around the radix point. Zero digits
+// past the last one are mapped too, until they are too small to matter
+fn radical_inverse<F: FnMut(u32) -> u32>(base: u32, mut index: u32, mut map_digit: F) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut factor = inverse_base;
+    let mut result = 0.0;
+
+    while factor > 1e-12 {
+        result += factor * map_digit(index % base) as f64;
+        index /= base;
+        factor *= inverse_base;
+    }
+
+    result.min(1.0 - f64::EPSILON)
+}
+
+// Position of the index in random permutation of 0..count picked by the seed (Kensler, 2013)
+fn permute(mut index: u32, count: u32, seed: u32) -> u32 {
+    let mut mask = count - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= mask;
+        index ^= index >> 5;
+
+        if index < count {
+            return (index.wrapping_add(seed)) % count;
+        }
+    }
+}
+
+fn sobol(index: u32, dimension: usize) -> u32 {
+    let mut result = 0;
+
+    for (bit, direction) in SOBOL_DIRECTIONS[dimension].iter().enumerate() {
+        if index >> bit & 1 == 1 {
+            result ^= direction;
+        }
+    }
+
+    result
+}
+
+// Owen scrambling of the bits, hash based version by Burley (2020)
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    let mut x = value.reverse_bits().wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+
+    x.reverse_bits()
+}
+
+// Direction numbers of the first four Sobol dimensions, the first one is van der Corput
+// sequence and the rest use primitive polynomials from Joe and Kuo
+const fn sobol_directions() -> [[u32; 32]; 4] {
+    let degrees = [1, 2, 3];
+    let coefficients = [0, 1, 1];
+    let initial = [[1, 0, 0], [1, 3, 0], [1, 3, 1]];
+
+    let mut directions = [[0u32; 32]; 4];
+    let mut k = 0;
+
+    while k < 32 {
+        directions[0][k] = 1 << (31 - k);
+        k += 1;
+    }
+
+    let mut d = 1;
+
+    while d < 4 {
+        let degree = degrees[d - 1];
+        let coefficient = coefficients[d - 1];
+        let mut k = 0;
+
+        while k < 32 {
+            if k < degree {
+                directions[d][k] = initial[d - 1][k] << (31 - k);
+            } else {
+                let mut x = directions[d][k - degree] ^ (directions[d][k - degree] >> degree);
+                let mut j = 1;
+
+                while j < degree {
+                    if (coefficient >> (degree - 1 - j)) & 1 == 1 {
+                        x ^= directions[d][k - j];
+                    }
+
+                    j += 1;
+                }
+
+                directions[d][k] = x;
+            }
+
+            k += 1;
+        }
+
+        d += 1;
+    }
+
+    directions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol];
+
+    // Whether every one of count equal intervals of [0, 1) holds exactly one value
+    fn fills_intervals(values: &[f64], count: usize) -> bool {
+        let mut intervals: Vec<usize> = values.iter().map(|v| (v * count as f64) as usize).collect();
+        intervals.sort();
+
+        intervals == (0..count).collect::<Vec<_>>()
+    }
+
+    // Numbers of the dimension for samples 0..count
+    fn dimension(kind: SamplerKind, seed: u64, samples: u32, dimension: u32, count: u32) -> Vec<f64> {
+        let mut sampler = Sampler::new(kind, seed, samples);
+
+        (0..count)
+            .map(|index| {
+                sampler.start_sample(index);
+                (0..dimension).for_each(|_| { sampler.next_1d(); });
+
+                sampler.next_1d()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn values_in_unit_interval() {
+        for &kind in KINDS.iter() {
+            for seed in 0..4 {
+                let mut sampler = Sampler::new(kind, derive_seed(seed, 7), 16);
+
+                // Past the expected samples and the tabulated primes and Sobol dimensions too
+                for index in 0..64 {
+                    sampler.start_sample(index);
+
+                    for _ in 0..40 {
+                        let value = sampler.next_1d();
+                        assert!((0.0..1.0).contains(&value), "{:?} gave {}", kind, value);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_fills_elementary_intervals() {
+        for k in 0..10 {
+            let count = 1 << k;
+
+            for dim in 0..4 {
+                let values: Vec<f64> = (0..count).map(|i| sobol(i, dim) as f64 / 4294967296.0).collect();
+                assert!(fills_intervals(&values, count as usize), "dimension {} with {} points", dim, count);
+            }
+
+            for dim in 0..8 {
+                let values = dimension(SamplerKind::Sobol, 42, count, dim, count);
+                assert!(fills_intervals(&values, count as usize), "scrambled dimension {} with {} points", dim, count);
+            }
+        }
+    }
+
+    #[test]
+    fn permute_is_bijection() {
+        for &count in [1, 2, 3, 5, 16, 17, 100, 1000].iter() {
+            for seed in 0..8 {
+                let seed = hash(seed) as u32;
+                let mut permuted: Vec<u32> = (0..count).map(|i| permute(i, count, seed)).collect();
+                permuted.sort();
+
+                assert_eq!(permuted, (0..count).collect::<Vec<_>>(), "count {} seed {}", count, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn radical_inverse_base_2_is_van_der_corput() {
+        for index in (0..1000).chain([u32::MAX - 1, u32::MAX].iter().cloned()) {
+            let expected = index.reverse_bits() as f64 / 4294967296.0;
+
+            assert_eq!(radical_inverse(2, index, |digit| digit), expected, "index {}", index);
+        }
+
+        // 5 is 12 in base 3
+        assert!((radical_inverse(3, 5, |digit| digit) - 7.0 / 9.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn halton_keeps_elementary_intervals() {
+        for k in 0..8 {
+            let values = dimension(SamplerKind::Halton, 42, 1, 0, 1 << k);
+            assert!(fills_intervals(&values, 1 << k), "base 2 with {} points", 1 << k);
+        }
+
+        let values = dimension(SamplerKind::Halton, 42, 1, 1, 27);
+        assert!(fills_intervals(&values, 27), "base 3 with 27 points");
+    }
+
+    #[test]
+    fn stratified_fills_strata() {
+        for &samples in [1, 7, 16].iter() {
+            for dim in 0..6 {
+                let values = dimension(SamplerKind::Stratified, 42, samples, dim, samples);
+                assert!(fills_intervals(&values, samples as usize), "dimension {} with {} samples", dim, samples);
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_and_index_repeat() {
+        for &kind in KINDS.iter() {
+            let draw = |seed: u64, index: u32| {
+                let mut sampler = Sampler::new(kind, seed, 16);
+                sampler.start_sample(index);
+
+                (0..10).map(|_| sampler.next_1d()).collect::<Vec<_>>()
+            };
+
+            assert_eq!(draw(5, 3), draw(5, 3), "{:?}", kind);
+            assert_ne!(draw(5, 3), draw(6, 3), "{:?}", kind);
+            assert_ne!(draw(5, 3), draw(5, 4), "{:?}", kind);
+        }
+    }
+}
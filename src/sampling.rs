@@ -0,0 +1,86 @@
+use vector::{Vector3, Point};
+use sampler::Sampler;
+use std::f64::consts::PI;
+
+// Weight of sample from strategy with density `pdf` combined with strategy of `other_pdf` density
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+
+    if a + b == 0.0 { 0.0 } else { a / (a + b) }
+}
+
+// Picks direction from the point towards the sphere uniformly inside the cone the sphere
+// covers. Returns direction and its solid angle density, points inside the sphere get nothing
+pub fn sample_sphere(center: &Point, radius: f64, point: &Point, sampler: &mut Sampler) -> Option<(Vector3, f64)> {
+    // Numbers are drawn even for points inside, so following samples keep their dimensions
+    let (r1, r2) = sampler.next_2d();
+    let to_center = center.subtract(point);
+    let distance2 = to_center.norm();
+
+    if distance2 <= radius * radius {
+        return None;
+    }
+
+    let cos_max = (1.0 - radius * radius / distance2).sqrt();
+    let cos_theta = 1.0 - r1 * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * r2;
+
+    let (u, v, w) = basis(&to_center.normalize());
+
+    let direction = u.multiply(sin_theta * phi.cos())
+        .add(&v.multiply(sin_theta * phi.sin()))
+        .add(&w.multiply(cos_theta));
+
+    Some((direction, cone_pdf(cos_max)))
+}
+
+// Maps two numbers in [0, 1) to uniformly distributed direction
+pub fn uniform_sphere(u: f64, v: f64) -> Vector3 {
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Density of sample_sphere picking any direction which hits the sphere
+pub fn sphere_pdf(center: &Point, radius: f64, point: &Point) -> f64 {
+    let distance2 = center.subtract(point).norm();
+
+    if distance2 <= radius * radius {
+        return 0.0;
+    }
+
+    cone_pdf((1.0 - radius * radius / distance2).sqrt())
+}
+
+// Distance along normalized direction to the nearest sphere hit in front of the origin
+pub fn intersect_sphere(center: &Point, radius: f64, origin: &Point, direction: &Vector3) -> Option<f64> {
+    let l = origin.subtract(center);
+    let b = l.dot(direction);
+    let c = l.norm() - radius * radius;
+    let disc = b * b - c;
+
+    if disc < 0.0 {
+        return None;
+    }
+
+    let root = disc.sqrt();
+
+    [-b - root, -b + root].iter().cloned().find(|&t| t > 1e-6)
+}
+
+fn cone_pdf(cos_max: f64) -> f64 {
+    1.0 / (2.0 * PI * (1.0 - cos_max).max(1e-12))
+}
+
+// Orthonormal basis with w along given normalized vector
+fn basis(w: &Vector3) -> (Vector3, Vector3, Vector3) {
+    let helper = if w.x.abs() > 0.9 { Vector3::new(0.0, 1.0, 0.0) } else { Vector3::new(1.0, 0.0, 0.0) };
+    let u = helper.cross(w).normalize();
+    let v = w.cross(&u);
+
+    (u, v, w.clone())
+}
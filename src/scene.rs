@@ -1,101 +1,277 @@
 use geometry::{Object, Intersectable};
 use light::Light;
 use vector::{Vector2, Vector3, Point};
-use intersection::Intersection;
+use intersection::{Intersection, Surface};
 use ray::Ray;
 use color::Color;
 use camera::Camera;
+use texture::{TextureCoords, TextureCache};
+use error::SceneError;
+use integrator::Integrator;
+use denoise::Denoiser;
+use tiles::Tiles;
+use format::SceneFormat;
+use material::Material;
+use sampler::Sampler;
+use preprocess;
+use lint;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-#[derive(Deserialize, Debug)]
+// Distance ray is pushed past cut out hit before looking for the next one
+const ALPHA_BIAS: f64 = 1e-4;
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Scene {
     pub width: u32,
     pub height: u32,
     pub camera: Camera,
     pub geometry: Vec<Object>,
     pub lights: Vec<Light>,
+
+    // Named textures which materials can reference instead of file paths
+    #[serde(default, skip_serializing_if="HashMap::is_empty")]
+    pub textures: HashMap<String, PathBuf>,
+
+    // Named materials which objects can reference instead of defining their own
+    #[serde(default, skip_serializing_if="HashMap::is_empty")]
+    pub materials: HashMap<String, Material>,
+
+    // Algorithm computing light arriving along camera rays
+    #[serde(default)]
+    pub integrator: Integrator,
+
+    // Size and order of tiles the image is rendered in
+    #[serde(default, skip_serializing_if="Tiles::is_default")]
+    pub tiles: Tiles,
+
+    // Filter removing noise from the rendered image
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub denoiser: Option<Denoiser>,
+
+    // Convert loaded textures to linear floats. Faster lookups at cost of memory
+    #[serde(default)]
+    pub linear_textures: bool,
 }
 
 impl Scene {
+    // Reads scene file, checks its values and loads textures relative to the scene folder
+    pub fn load(path: &Path) -> Result<Scene, SceneError> {
+        let folder = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+        Scene::load_with_search_paths(path, &[folder])
+    }
+
+    pub fn load_with_search_paths(path: &Path, search_paths: &[PathBuf]) -> Result<Scene, SceneError> {
+        let mut scene = Scene::parse(path)?;
+
+        scene.validate()?;
+        scene.load_textures(search_paths)?;
+
+        Ok(scene)
+    }
+
+    // Reads scene file without checking values and loading textures. Format is picked by extension.
+    // Variables are substituted and included files merged in, so the result is self contained
+    pub fn parse(path: &Path) -> Result<Scene, SceneError> {
+        let source = preprocess::read(path, &HashMap::new())?;
+        let mut scene: Scene = source.format.parse(&source.contents)?;
+        let mut stack = fs::canonicalize(path).into_iter().collect();
+
+        for include in &source.include {
+            preprocess::include(&mut scene, include, &source.variables, &mut stack)?;
+        }
+
+        scene.resolve_materials()?;
+
+        Ok(scene)
+    }
+
+    // Writes scene in format picked by extension. Textures are stored as paths they were referenced with
+    pub fn save(&self, path: &Path) -> Result<(), SceneError> {
+        let contents = SceneFormat::from_path(path)?.write(self)?;
+
+        fs::write(path, contents)?;
+
+        Ok(())
+    }
+
+    // Replaces object materials given by name with copies of scene materials
+    pub fn resolve_materials(&mut self) -> Result<(), SceneError> {
+        for (i, object) in self.geometry.iter_mut().enumerate() {
+            if let Some(name) = object.material().name.clone() {
+                match self.materials.get(&name) {
+                    Some(named) => *object.material_mut() = Material { name: Some(name), ..named.clone() },
+                    None => {
+                        let location = format!("geometry[{}].material", i);
+                        return Err(SceneError::invalid_value(&location, &format!("unknown material '{}'", name)));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Checks values which deserialize fine but can't be rendered
+    pub fn validate(&self) -> Result<(), SceneError> {
+        match lint::check(self).into_iter().find(|issue| issue.is_error()) {
+            Some(issue) => Err(SceneError::InvalidValue { location: issue.location, message: issue.message }),
+            None => Ok(())
+        }
+    }
+
+    // Loads images for all textures used by materials. Each file is read once and shared.
+    // Relative paths are looked up in search paths in order, usually it's the scene file folder
+    pub fn load_textures(&mut self, search_paths: &[PathBuf]) -> Result<(), SceneError> {
+        let mut cache = TextureCache::new(self.linear_textures);
+
+        for object in self.geometry.iter_mut() {
+            for texture in object.textures_mut() {
+                let path = resolve_texture_path(&self.textures, &texture.source, search_paths);
+
+                match cache.load(&path) {
+                    Ok(image) => texture.image = Some(image),
+                    Err(error) => return Err(SceneError::MissingAsset { path, error })
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // File path of texture referenced by name or path
+    pub fn texture_path(&self, source: &str, search_paths: &[PathBuf]) -> PathBuf {
+        resolve_texture_path(&self.textures, source, search_paths)
+    }
+
     pub fn trace(&self, ray: &Ray) -> Option<Intersection> {
         self.geometry.iter()
-            .filter_map(|o| o.intersect(ray).map(|d| Intersection::new(d, o)))
+            .filter_map(|o| self.intersect(o, ray).map(|d| Intersection::new(d, o)))
             .min_by(|i1, i2| {
                 i1.distance.partial_cmp(&i2.distance).unwrap()
             })
     }
 
-    pub fn get_color(&self, ray: &Ray, diffuse_depth: u32) -> Color {
-        let mut color = Color::black();
+    // Same as Object::intersect but skips hits falling into cut out regions of alpha mask
+    fn intersect(&self, object: &Object, ray: &Ray) -> Option<f64> {
+        let material = object.material();
 
-        if diffuse_depth == 0 {
-            return color;
+        if material.alpha_mask.is_none() {
+            return object.intersect(ray);
+        }
+
+        let mut travelled = 0.0;
+        let mut origin = ray.origin.clone();
+
+        loop {
+            let distance = object.intersect(&Ray { origin: origin.clone(), direction: ray.direction.clone() })?;
+            let hit_point = origin.add(&ray.direction.multiply(distance));
+
+            if !material.is_cut_out(&Self::texture_coords(object, &hit_point)) {
+                return Some(travelled + distance);
+            }
+
+            travelled += distance + ALPHA_BIAS;
+            origin = hit_point.add(&ray.direction.multiply(ALPHA_BIAS));
         }
+    }
+
+    fn texture_coords(object: &Object, hit_point: &Point) -> TextureCoords {
+        let mut texture_coords = TextureCoords {
+            uv: Vector2 { x: 0.0, y: 0.0 },
+            world: hit_point.clone(),
+            object: object.object_coords(hit_point),
+        };
+
+        if object.material().uses_texture() {
+            texture_coords.uv = object.texture_coords(hit_point);
+        }
+
+        texture_coords
+    }
+
+    // Hit point, shading normal and texture coordinates of ray intersection
+    pub fn surface(&self, ray: &Ray, intersection: &Intersection) -> Surface {
+        let object = intersection.object;
+        let material = object.material();
+        let point = ray.origin.add(&ray.direction.multiply(intersection.distance));
+        let mut normal = object.surface_normal(&point);
+
+        let coords = Self::texture_coords(object, &point);
+
+        if material.perturbs_normal() {
+            normal = material.shading_normal(&normal, &object.tangent(&point), &object.bitangent(&point), &coords);
+        }
+
+        Surface { point, normal, coords }
+    }
 
+    // Color of the sky seen in given direction
+    pub fn background(&self, direction: &Vector3) -> Color {
         // TODO: Move that to scene config
         let bg_start_color = Color { r: 1.0, g: 1.0 , b: 1.0 };
         let bg_end_color = Color { r: 0.5, g: 0.7 , b: 1.0 };
 
-        match self.trace(ray) {
-            Some(intersection) => {
+        let t = 0.5 * (direction.y + 1.0);
+        Color::lerp(&bg_start_color, &bg_end_color, t)
+    }
 
-                let object = intersection.object;
-                let material = object.material();
-                let hit_point = ray.origin.add(&ray.direction.multiply(intersection.distance));
-                let surface_normal = object.surface_normal(&hit_point);
+    pub fn get_color(&self, ray: &Ray, diffuse_depth: u32, sampler: &mut Sampler) -> Color {
+        let mut color = Color::black();
 
-                let mut texture_coords = Vector2 { x: 0.0, y: 0.0 };
-                if material.uses_texture() {
-                    texture_coords = object.texture_coords(&hit_point);
-                }
+        if diffuse_depth == 0 {
+            return color;
+        }
+
+        match self.trace(ray) {
+            Some(intersection) => {
+                let material = intersection.object.material();
+                let surface = self.surface(ray, &intersection);
+                let hit_point = surface.point;
+                let surface_normal = surface.normal;
+                let texture_coords = surface.coords;
 
-                let light_reflected = material.albedo / ::std::f64::consts::PI;
+                let albedo = material.albedo.value_at(&texture_coords);
+                let light_reflected = albedo / ::std::f64::consts::PI;
                 let light_color = self.light_color_at_hit_point(&hit_point, &surface_normal)
                     .multiply(light_reflected);
 
-                let (diffuse_vector, main_color) = material.scatter(&ray.direction, &surface_normal, &texture_coords);
+                let scatter = material.scatter_legacy(&ray.direction, &surface_normal, &texture_coords, sampler);
+                let main_color = scatter.attenuation;
 
                 let diffuse_ray = Ray {
                     origin: hit_point.clone(),
-                    direction: diffuse_vector.normalize()
+                    direction: scatter.direction.normalize()
                 };
 
-                let diffuse_color = self.get_color(&diffuse_ray, diffuse_depth - 1)
-                    .multiply(1.0 - material.albedo);
+                let diffuse_color = self.get_color(&diffuse_ray, diffuse_depth - 1, sampler)
+                    .multiply(1.0 - albedo);
 
                 let highlighted_color = main_color.multiply_color(&light_color);
 
-                color = main_color.multiply_color(&diffuse_color).add_color(&highlighted_color);
+                color = main_color.multiply_color(&diffuse_color)
+                    .add_color(&highlighted_color)
+                    .add_color(&material.emission);
             },
 
             None => {
-                let t = 0.5 * (ray.direction.y + 1.0);
-                color = Color::lerp(&bg_start_color, &bg_end_color, t);
+                color = self.background(&ray.direction);
             }
         }
 
         color
     }
 
-    fn light_color_at_hit_point(&self, hit_point: &Point, normal: &Vector3) -> Color {
+    // Light arriving at the point from all light sources, weighted by cosine to the normal
+    pub fn light_color_at_hit_point(&self, hit_point: &Point, normal: &Vector3) -> Color {
         let mut color = Color::black();
 
         for light in self.lights.iter() {
             let direction_to_light = light.direction_vector(&hit_point).normalize().neg();
 
-            let in_light = if light.cast_shadow() {
-                let shadow_ray = Ray {
-                    origin: hit_point.add(&normal.multiply(1e-13)), // add tiny shadow bias to remove artifacts
-                    direction: direction_to_light.clone(),
-                };
-
-                let shadow_intersection = self.trace(&shadow_ray);
-
-                shadow_intersection.is_none() || shadow_intersection.unwrap().distance > light.distance(&hit_point)
-            } else {
-                true
-            };
-
-            let light_intensity = if in_light { light.relative_intensity(&hit_point) } else { 0.0 };
+            let light_intensity = if self.in_light(hit_point, normal, light) { light.relative_intensity(hit_point) } else { 0.0 };
             let light_power = normal.dot(&direction_to_light).max(0.0) * light_intensity;
 
             color = color.add_color(&light.color().multiply(light_power));
@@ -103,4 +279,79 @@ impl Scene {
 
         color
     }
+
+    // Checks whether light reaches the point or is blocked by geometry
+    pub fn in_light(&self, hit_point: &Point, normal: &Vector3, light: &Light) -> bool {
+        if !light.cast_shadow() {
+            return true;
+        }
+
+        let shadow_ray = Ray {
+            origin: hit_point.add(&normal.multiply(1e-13)), // add tiny shadow bias to remove artifacts
+            direction: light.direction_vector(hit_point).normalize().neg(),
+        };
+
+        let shadow_intersection = self.trace(&shadow_ray);
+
+        shadow_intersection.is_none() || shadow_intersection.unwrap().distance > light.distance(hit_point)
+    }
+}
+
+// Returns first existing candidate of relative path in search paths. If nothing
+// is found the path is resolved against the first search path so errors point there
+pub fn resolve_path(path: &Path, search_paths: &[PathBuf]) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+
+    search_paths.iter()
+        .map(|dir| dir.join(path))
+        .find(|candidate| candidate.exists())
+        .or_else(|| search_paths.first().map(|dir| dir.join(path)))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+fn resolve_texture_path(textures: &HashMap<String, PathBuf>, source: &str, search_paths: &[PathBuf]) -> PathBuf {
+    match textures.get(source) {
+        Some(path) => resolve_path(path, search_paths),
+        None => resolve_path(Path::new(source), search_paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use material::Coloration;
+    use serde_json;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn edited_material_is_saved_inline() {
+        let folder = env::temp_dir().join(format!("raytracer-scene-{}", process::id()));
+        fs::create_dir_all(&folder).unwrap();
+
+        let sphere = r#"{ "Sphere": { "center": { "x": 0.0, "y": 0.0, "z": -5.0 }, "radius": 1.0, "material": "red" } }"#;
+        let contents = format!(r#"{{ "width": 4, "height": 2, "lights": [], "geometry": [{}, {}],
+            "camera": {{ "fov": 60.0, "samples": 1, "diffuse": 1, "position": {{ "x": 0.0, "y": 0.0, "z": 0.0 }} }},
+            "materials": {{ "red": {{ "color": {{ "Color": {{ "r": 1.0, "g": 0.0, "b": 0.0 }} }} }} }} }}"#, sphere, sphere);
+        fs::write(folder.join("scene.json"), contents).unwrap();
+
+        let mut scene = Scene::load(&folder.join("scene.json")).unwrap();
+        scene.geometry[0].material_mut().color = Coloration::Color(Color { r: 0.0, g: 1.0, b: 0.0 });
+        scene.save(&folder.join("saved.json")).unwrap();
+
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(folder.join("saved.json")).unwrap()).unwrap();
+        assert_eq!(saved["geometry"][0]["Sphere"]["material"]["color"]["Color"]["g"], 1.0);
+        assert_eq!(saved["geometry"][1]["Sphere"]["material"], "red");
+
+        let reloaded = Scene::load(&folder.join("saved.json")).unwrap();
+        assert_eq!(reloaded.geometry[0].material().name, None);
+        assert_eq!(reloaded.geometry[1].material().name, Some("red".to_string()));
+
+        match reloaded.geometry[0].material().color {
+            Coloration::Color(ref color) => assert_eq!((color.r, color.g), (0.0, 1.0)),
+            ref other => panic!("saved color {:?}", other)
+        }
+    }
 }
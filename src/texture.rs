@@ -0,0 +1,352 @@
+use image;
+use image::{ImageResult, RgbaImage};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::ser::SerializeStruct;
+use serde::de::{self, Visitor, MapAccess};
+use serde::de::value::MapAccessDeserializer;
+use color::Color;
+use vector::{Vector2, Vector3, Point};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct Texture {
+    // Either name of texture registered in the scene or path to image file
+    pub source: String,
+    pub image: Option<Arc<TextureImage>>,
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+    pub scale: Vector2,
+    pub offset: Vector2,
+    pub rotation: f64,
+}
+
+// Hit point description used for texture lookups
+#[derive(Debug, Clone)]
+pub struct TextureCoords {
+    pub uv: Vector2,
+    pub world: Point,
+    pub object: Point,
+}
+
+impl TextureCoords {
+    // Moves lookup point by given uv delta, world and object points are moved along direction
+    pub fn shifted(&self, du: f64, dv: f64, direction: &Vector3) -> TextureCoords {
+        let delta = direction.multiply(du + dv);
+
+        TextureCoords {
+            uv: Vector2 { x: self.uv.x + du, y: self.uv.y + dv },
+            world: self.world.add(&delta),
+            object: self.object.add(&delta),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    Mirror,
+    Clamp,
+    Border(Color)
+}
+
+// Decoded image shared between all textures referencing the same file
+pub enum TextureImage {
+    // 8 bit sRGB pixels, decoded to linear color on every lookup
+    Encoded(RgbaImage),
+    // pixels converted to linear floats once on load
+    Linear {
+        width: u32,
+        height: u32,
+        pixels: Vec<[f32; 4]>,
+    }
+}
+
+// Loads every image file once, files are matched by canonical path
+#[derive(Default)]
+pub struct TextureCache {
+    pub linear: bool,
+    images: HashMap<PathBuf, Arc<TextureImage>>,
+}
+
+#[derive(Deserialize)]
+struct TextureOptions {
+    path: String,
+    #[serde(default)]
+    wrap_u: WrapMode,
+    #[serde(default)]
+    wrap_v: WrapMode,
+    #[serde(default="Texture::default_scale")]
+    scale: Vector2,
+    #[serde(default="Texture::default_offset")]
+    offset: Vector2,
+    #[serde(default)]
+    rotation: f64,
+}
+
+impl Texture {
+    fn default_scale() -> Vector2 { Vector2 { x: 1.0, y: 1.0 } }
+    fn default_offset() -> Vector2 { Vector2 { x: 0.0, y: 0.0 } }
+
+    pub fn new(source: &str) -> Texture {
+        Texture {
+            source: source.to_string(),
+            image: None,
+            wrap_u: WrapMode::default(),
+            wrap_v: WrapMode::default(),
+            scale: Self::default_scale(),
+            offset: Self::default_offset(),
+            rotation: 0.0,
+        }
+    }
+
+    fn has_default_options(&self) -> bool {
+        matches!(self.wrap_u, WrapMode::Repeat) && matches!(self.wrap_v, WrapMode::Repeat) &&
+            self.scale.x == 1.0 && self.scale.y == 1.0 &&
+            self.offset.x == 0.0 && self.offset.y == 0.0 &&
+            self.rotation == 0.0
+    }
+
+    pub fn color_at(&self, coords: &TextureCoords) -> Color {
+        match self.lookup(coords) {
+            Ok((image, x, y)) => image.color(x, y),
+            Err(color) => color
+        }
+    }
+
+    // Reads texel as tangent space vector, components are mapped from [0, 1] to [-1, 1]
+    pub fn vector_at(&self, coords: &TextureCoords) -> Vector3 {
+        // Missing image leaves the normal unchanged instead of turning it inside out
+        if self.image.is_none() {
+            return Vector3::new(0.0, 0.0, 1.0);
+        }
+
+        let color = self.raw_at(coords);
+
+        Vector3::new(2.0 * color.r - 1.0, 2.0 * color.g - 1.0, 2.0 * color.b - 1.0)
+    }
+
+    // Reads texel as stored in the file for non-color data like normals or roughness
+    pub fn raw_at(&self, coords: &TextureCoords) -> Color {
+        match self.lookup(coords) {
+            Ok((image, x, y)) => image.raw(x, y),
+            Err(color) => color
+        }
+    }
+
+    // Reads alpha channel of the image. Outside of bordered texture luminance of border color is used
+    pub fn alpha_at(&self, coords: &TextureCoords) -> f64 {
+        match self.lookup(coords) {
+            Ok((image, x, y)) => image.alpha(x, y),
+            Err(color) => color.luminance()
+        }
+    }
+
+    // Textures of scenes deserialized without Scene::load_textures have no image, they read as black
+    fn lookup(&self, coords: &TextureCoords) -> Result<(&TextureImage, u32, u32), Color> {
+        let image = self.image.as_ref().ok_or_else(Color::black)?;
+        let uv = self.transform(&coords.uv);
+
+        let x = self.wrap_u.apply(uv.x)?;
+        let y = self.wrap_v.apply(uv.y)?;
+
+        Ok((image, Self::texel(x, image.width()), Self::texel(y, image.height())))
+    }
+
+    // Applies scale, then rotation (in degrees) around the origin, then offset
+    fn transform(&self, coords: &Vector2) -> Vector2 {
+        let x = coords.x * self.scale.x;
+        let y = coords.y * self.scale.y;
+
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+
+        Vector2 {
+            x: x * cos - y * sin + self.offset.x,
+            y: x * sin + y * cos + self.offset.y,
+        }
+    }
+
+    fn texel(val: f64, bound: u32) -> u32 {
+        ((val * bound as f64) as u32).min(bound - 1)
+    }
+}
+
+impl TextureImage {
+    pub fn width(&self) -> u32 {
+        match *self {
+            TextureImage::Encoded(ref image) => image.width(),
+            TextureImage::Linear { width, .. } => width
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        match *self {
+            TextureImage::Encoded(ref image) => image.height(),
+            TextureImage::Linear { height, .. } => height
+        }
+    }
+
+    // Linear color of the pixel
+    pub fn color(&self, x: u32, y: u32) -> Color {
+        match *self {
+            TextureImage::Encoded(ref image) => Color::from_rgba(*image.get_pixel(x, y)),
+            TextureImage::Linear { width, ref pixels, .. } => {
+                let p = pixels[(y * width + x) as usize];
+
+                Color { r: p[0] as f64, g: p[1] as f64, b: p[2] as f64 }
+            }
+        }
+    }
+
+    // Pixel values as stored in the file, used for non-color data
+    pub fn raw(&self, x: u32, y: u32) -> Color {
+        match *self {
+            TextureImage::Encoded(ref image) => Color::from_rgba_linear(*image.get_pixel(x, y)),
+            TextureImage::Linear { .. } => self.color(x, y).gamma_encode()
+        }
+    }
+
+    pub fn alpha(&self, x: u32, y: u32) -> f64 {
+        match *self {
+            TextureImage::Encoded(ref image) => (image.get_pixel(x, y).data[3] as f64) / 255.0,
+            TextureImage::Linear { width, ref pixels, .. } => pixels[(y * width + x) as usize][3] as f64
+        }
+    }
+
+    fn to_linear(image: RgbaImage) -> TextureImage {
+        let pixels = image.pixels().map(|p| {
+            let color = Color::from_rgba(*p);
+
+            [color.r as f32, color.g as f32, color.b as f32, p.data[3] as f32 / 255.0]
+        }).collect();
+
+        TextureImage::Linear {
+            width: image.width(),
+            height: image.height(),
+            pixels,
+        }
+    }
+}
+
+impl TextureCache {
+    pub fn new(linear: bool) -> TextureCache {
+        TextureCache {
+            linear,
+            images: HashMap::new(),
+        }
+    }
+
+    pub fn load(&mut self, path: &Path) -> ImageResult<Arc<TextureImage>> {
+        let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        if let Some(image) = self.images.get(&key) {
+            return Ok(image.clone());
+        }
+
+        let rgba = image::open(&key)?.to_rgba();
+        let image = Arc::new(if self.linear { TextureImage::to_linear(rgba) } else { TextureImage::Encoded(rgba) });
+
+        self.images.insert(key, image.clone());
+
+        Ok(image)
+    }
+}
+
+impl WrapMode {
+    // Maps coordinate into [0, 1] range or returns border color if it falls outside
+    fn apply(&self, val: f64) -> Result<f64, Color> {
+        match *self {
+            WrapMode::Repeat => Ok(val - val.floor()),
+            WrapMode::Mirror => {
+                let t = val - (val / 2.0).floor() * 2.0;
+
+                Ok(if t > 1.0 { 2.0 - t } else { t })
+            },
+            WrapMode::Clamp => Ok(val.clamp(0.0, 1.0)),
+            WrapMode::Border(ref color) => {
+                if (0.0..=1.0).contains(&val) { Ok(val) } else { Err(color.clone()) }
+            }
+        }
+    }
+}
+
+impl From<TextureOptions> for Texture {
+    fn from(options: TextureOptions) -> Texture {
+        Texture {
+            wrap_u: options.wrap_u,
+            wrap_v: options.wrap_v,
+            scale: options.scale,
+            offset: options.offset,
+            rotation: options.rotation,
+            ..Texture::new(&options.path)
+        }
+    }
+}
+
+// Textures with default sampling options are written as plain path, image data is never stored
+impl Serialize for Texture {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        if self.has_default_options() {
+            return serializer.serialize_str(&self.source);
+        }
+
+        let mut state = serializer.serialize_struct("Texture", 6)?;
+        state.serialize_field("path", &self.source)?;
+        state.serialize_field("wrap_u", &self.wrap_u)?;
+        state.serialize_field("wrap_v", &self.wrap_v)?;
+        state.serialize_field("scale", &self.scale)?;
+        state.serialize_field("offset", &self.offset)?;
+        state.serialize_field("rotation", &self.rotation)?;
+        state.end()
+    }
+}
+
+// Texture can be given either as a plain path or as an object with path and sampling options
+impl<'de> Deserialize<'de> for Texture {
+    fn deserialize<D>(deserializer: D) -> Result<Texture, D::Error>
+        where D: Deserializer<'de>
+    {
+        struct TextureVisitor;
+
+        impl<'de> Visitor<'de> for TextureVisitor {
+            type Value = Texture;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("texture path or texture options")
+            }
+
+            fn visit_str<E>(self, path: &str) -> Result<Texture, E>
+                where E: de::Error
+            {
+                Ok(Texture::new(path))
+            }
+
+            fn visit_map<M>(self, map: M) -> Result<Texture, M::Error>
+                where M: MapAccess<'de>
+            {
+                TextureOptions::deserialize(MapAccessDeserializer::new(map)).map(Texture::from)
+            }
+        }
+
+        deserializer.deserialize_any(TextureVisitor)
+    }
+}
+
+impl fmt::Debug for Texture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Texture")
+            .field("source", &self.source)
+            .field("wrap_u", &self.wrap_u)
+            .field("wrap_v", &self.wrap_v)
+            .field("scale", &self.scale)
+            .field("offset", &self.offset)
+            .field("rotation", &self.rotation)
+            .finish()
+    }
+}
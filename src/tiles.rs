@@ -0,0 +1,214 @@
+// Rendering splits the image into square tiles which worker threads take one by one from
+// a shared queue. Every tile is rendered into its own buffer and copied into the image when done
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tiles {
+    // Side of a tile in pixels, tiles at the right and bottom edge can be smaller
+    #[serde(default="Tiles::default_size")]
+    pub size: u32,
+    #[serde(default)]
+    pub order: TileOrder,
+}
+
+// Order tiles are handed to workers in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum TileOrder {
+    // From the center of the image outwards, the most interesting part is usually done first
+    #[default]
+    Spiral,
+    // Along Hilbert curve, consecutive tiles are neighbours which keeps geometry and textures in cache
+    Hilbert,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for Tiles {
+    fn default() -> Tiles {
+        Tiles { size: Tiles::default_size(), order: TileOrder::default() }
+    }
+}
+
+impl Tiles {
+    fn default_size() -> u32 { 32 }
+
+    pub fn is_default(&self) -> bool {
+        *self == Tiles::default()
+    }
+
+    // Tiles covering the image in render order
+    pub fn split(&self, width: u32, height: u32) -> Vec<Tile> {
+        let size = self.size.max(1);
+        let columns = width.div_ceil(size);
+        let rows = height.div_ceil(size);
+
+        let cells = match self.order {
+            TileOrder::Spiral => spiral(columns, rows),
+            TileOrder::Hilbert => hilbert(columns, rows),
+        };
+
+        cells.into_iter()
+            .map(|(column, row)| {
+                let x = column * size;
+                let y = row * size;
+
+                Tile { x, y, width: size.min(width - x), height: size.min(height - y) }
+            })
+            .collect()
+    }
+}
+
+impl Tile {
+    pub fn pixels_count(&self) -> u32 {
+        self.width * self.height
+    }
+}
+
+// Walks square spiral around the center cell, cells outside of the grid are skipped
+fn spiral(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let total = (columns * rows) as usize;
+    let mut cells = Vec::with_capacity(total);
+    let (mut x, mut y) = (((columns as i64) - 1) / 2, ((rows as i64) - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut step = 1;
+    let mut direction = 0;
+
+    let visit = |x: i64, y: i64, cells: &mut Vec<(u32, u32)>| {
+        if x >= 0 && y >= 0 && x < columns as i64 && y < rows as i64 {
+            cells.push((x as u32, y as u32));
+        }
+    };
+
+    visit(x, y, &mut cells);
+
+    while cells.len() < total {
+        // Every step length is walked twice before it grows
+        for _ in 0..2 {
+            let (dx, dy) = directions[direction % 4];
+
+            for _ in 0..step {
+                x += dx;
+                y += dy;
+                visit(x, y, &mut cells);
+            }
+
+            direction += 1;
+        }
+
+        step += 1;
+    }
+
+    cells
+}
+
+// Hilbert curve over the smallest power of two square covering the grid, cells outside are skipped
+fn hilbert(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let side = columns.max(rows).next_power_of_two();
+
+    (0..side * side)
+        .map(|index| hilbert_cell(side, index))
+        .filter(|&(x, y)| x < columns && y < rows)
+        .collect()
+}
+
+// Cell at given distance along Hilbert curve filling side x side square
+fn hilbert_cell(side: u32, index: u32) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = index;
+    let mut s = 1;
+
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+
+            ::std::mem::swap(&mut x, &mut y);
+        }
+
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDERS: [TileOrder; 2] = [TileOrder::Spiral, TileOrder::Hilbert];
+
+    // Tiles of the image with 10 pixel tiles, edge tiles are clipped for sizes not divisible by 10
+    fn split(order: TileOrder, width: u32, height: u32) -> Vec<Tile> {
+        Tiles { size: 10, order }.split(width, height)
+    }
+
+    #[test]
+    fn covers_every_pixel_once() {
+        // Grids of 1x7, 7x1, 5x3, 3x5, 6x6, 0xN and Nx0 tiles
+        let images = [(10, 70), (70, 10), (45, 25), (25, 45), (60, 55), (0, 50), (50, 0)];
+
+        for &order in ORDERS.iter() {
+            for &(width, height) in images.iter() {
+                let mut counts = vec![0; (width * height) as usize];
+
+                for tile in split(order, width, height) {
+                    assert!(tile.pixels_count() > 0, "{:?} {}x{}: empty tile {:?}", order, width, height, tile);
+                    assert!(tile.x + tile.width <= width && tile.y + tile.height <= height, "{:?} {}x{}: tile {:?} outside", order, width, height, tile);
+
+                    for y in tile.y..tile.y + tile.height {
+                        for x in tile.x..tile.x + tile.width {
+                            counts[(y * width + x) as usize] += 1;
+                        }
+                    }
+                }
+
+                assert!(counts.iter().all(|&count| count == 1), "{:?} {}x{}", order, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn edge_tiles_are_clipped() {
+        for &order in ORDERS.iter() {
+            let tiles = split(order, 45, 25);
+            assert_eq!(tiles.len(), 15);
+
+            for tile in tiles {
+                assert_eq!(tile.width, if tile.x == 40 { 5 } else { 10 }, "{:?} {:?}", order, tile);
+                assert_eq!(tile.height, if tile.y == 20 { 5 } else { 10 }, "{:?} {:?}", order, tile);
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_starts_in_center() {
+        assert_eq!(spiral(5, 3)[0], (2, 1));
+        assert_eq!(spiral(1, 7)[0], (0, 3));
+    }
+
+    #[test]
+    fn consecutive_hilbert_cells_are_neighbours() {
+        for &side in [1, 2, 4, 8, 16].iter() {
+            let cells = hilbert(side, side);
+            assert_eq!(cells.len(), (side * side) as usize);
+            assert_eq!(cells[0], (0, 0));
+
+            for pair in cells.windows(2) {
+                let distance = (pair[0].0 as i64 - pair[1].0 as i64).abs() + (pair[0].1 as i64 - pair[1].1 as i64).abs();
+                assert_eq!(distance, 1, "side {}: {:?}", side, pair);
+            }
+        }
+    }
+}
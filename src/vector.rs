@@ -1,13 +1,11 @@
-use rand::prelude::*;
-
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Vector3 {
     pub x: f64,
     pub y: f64,
     pub z: f64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Vector2 {
     pub x: f64,
     pub y: f64
@@ -32,25 +30,6 @@ impl Vector3 {
         }
     }
 
-    pub fn random_unit() -> Vector3 {
-        let mut vec = Vector3 { x: 1.0, y: 1.0, z: 1.0 };
-        let mut rng = thread_rng();
-
-        while vec.norm() >= 1.0 {
-            let rx: f64 = rng.gen();
-            let ry: f64 = rng.gen();
-            let rz: f64 = rng.gen();
-
-            vec = Vector3 {
-                x: 2.0 * rx - 1.0,
-                y: 2.0 * ry - 1.0,
-                z: 2.0 * rz - 1.0
-            };
-        } {}
-
-        vec
-    }
-
     pub fn normalize(&self) -> Vector3 {
         let magnitude = self.magnitude();
 
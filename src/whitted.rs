@@ -0,0 +1,128 @@
+use color::Color;
+use material::Material;
+use ray::Ray;
+use scene::Scene;
+use texture::TextureCoords;
+use vector::{Vector3, Point};
+
+// Classic recursive ray tracer. It never picks anything at random, so the same scene always
+// renders to the same image
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Whitted {
+    // Pixels are sampled at centers of grid x grid cells, camera.samples is ignored
+    #[serde(default="Whitted::default_grid")]
+    pub grid: u32,
+    // Maximum number of mirror and refraction bounces
+    #[serde(default="Whitted::default_max_depth")]
+    pub max_depth: u32,
+    // Strength of Blinn-Phong highlights of lights
+    #[serde(default="Whitted::default_highlight")]
+    pub highlight: f64,
+    // Exponent of highlights, bigger values give smaller and sharper highlights
+    #[serde(default="Whitted::default_shininess")]
+    pub shininess: f64,
+}
+
+impl Default for Whitted {
+    fn default() -> Whitted {
+        Whitted {
+            grid: Whitted::default_grid(),
+            max_depth: Whitted::default_max_depth(),
+            highlight: Whitted::default_highlight(),
+            shininess: Whitted::default_shininess(),
+        }
+    }
+}
+
+impl Whitted {
+    fn default_grid() -> u32 { 1 }
+    fn default_max_depth() -> u32 { 5 }
+    fn default_highlight() -> f64 { 0.5 }
+    fn default_shininess() -> f64 { 32.0 }
+
+    pub fn radiance(&self, scene: &Scene, ray: &Ray) -> Color {
+        let ray = Ray { origin: ray.origin.clone(), direction: ray.direction.normalize() };
+
+        self.trace(scene, &ray, self.max_depth)
+    }
+
+    fn trace(&self, scene: &Scene, ray: &Ray, depth: u32) -> Color {
+        let intersection = match scene.trace(ray) {
+            Some(intersection) => intersection,
+            None => return scene.background(&ray.direction)
+        };
+
+        let material = intersection.object.material();
+        let surface = scene.surface(ray, &intersection);
+        let coords = &surface.coords;
+
+        // Lights shine on the side the ray came from, which is the inside for rays within glass
+        let facing = if surface.normal.dot(&ray.direction) > 0.0 { surface.normal.neg() } else { surface.normal.clone() };
+
+        let mut color = self.direct_light(scene, material, &surface.point, &facing, &ray.direction, coords)
+            .add_color(&material.emission);
+
+        if depth == 0 {
+            return color;
+        }
+
+        let opacity = material.opacity.value_at(coords);
+
+        // Mirror and refraction parts get the same shares Material::scatter picks them with.
+        // Light which isn't refracted is reflected, including total internal reflection
+        let mut reflected = material.reflection.value_at(coords).min(opacity);
+        let transmitted = 1.0 - opacity;
+
+        if transmitted > 0.0 {
+            match material.refraction(&ray.direction, &surface.normal, coords) {
+                Some((direction, reflection_prob)) => {
+                    let refracted_ray = Ray { origin: surface.point.clone(), direction: direction.normalize() };
+                    let refracted = self.trace(scene, &refracted_ray, depth - 1);
+
+                    color = color.add_color(&material.refraction_color.multiply_color(&refracted)
+                        .multiply(transmitted * (1.0 - reflection_prob)));
+
+                    reflected += transmitted * reflection_prob;
+                },
+                None => reflected += transmitted
+            }
+        }
+
+        if reflected > 0.0 {
+            let mirror_ray = Ray { origin: surface.point.clone(), direction: ray.direction.reflect(&surface.normal).normalize() };
+            let mirrored = self.trace(scene, &mirror_ray, depth - 1);
+
+            color = color.add_color(&material.reflection_color.multiply_color(&mirrored).multiply(reflected));
+        }
+
+        color
+    }
+
+    // Diffuse light and highlights from all lights which aren't shadowed. Area lights
+    // are treated as points at their centers, so shadows are hard
+    fn direct_light(&self, scene: &Scene, material: &Material, point: &Point, normal: &Vector3,
+                    direction: &Vector3, coords: &TextureCoords) -> Color {
+        let mut color = Color::black();
+        let diffuse = material.diffuse_brdf(coords);
+
+        for light in scene.lights.iter() {
+            let to_light = light.direction_vector(point).normalize().neg();
+            let cosine = normal.dot(&to_light);
+
+            if cosine <= 0.0 || !scene.in_light(point, normal, light) {
+                continue;
+            }
+
+            let irradiance = light.color().multiply(light.relative_intensity(point));
+
+            let half = to_light.subtract(direction).normalize();
+            let highlight = self.highlight * normal.dot(&half).max(0.0).powf(self.shininess);
+
+            color = color
+                .add_color(&diffuse.multiply_color(&irradiance).multiply(cosine))
+                .add_color(&irradiance.multiply(highlight));
+        }
+
+        color
+    }
+}